@@ -0,0 +1,136 @@
+use anyhow::{Result, bail};
+use lambda_http::http::HeaderMap;
+
+/// Who may call the api, configured with `API_KEYS` or explicitly turned off with `API_AUTH_DISABLED=true`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Auth {
+    Disabled,
+    ApiKeys(Vec<ApiKey>)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    /// Saved as `created_by` on the words added with this key
+    pub name: String,
+    pub key: String
+}
+
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl Auth {
+    /// `API_KEYS` is a comma separated list of `name:key` pairs, e.g. `gpt:s3cret,phone:0th3r`.
+    /// Refuses to start without keys unless `API_AUTH_DISABLED` is `true`, so a missing env var doesn't leave the api open.
+    pub fn from_env() -> Result<Self> {
+        if matches!(std::env::var("API_AUTH_DISABLED").as_deref(), Ok("true") | Ok("1")) {
+            tracing::warn!("API_AUTH_DISABLED is set, the api accepts unauthenticated requests");
+            return Ok(Auth::Disabled);
+        }
+
+        match std::env::var("API_KEYS") {
+            Ok(v) if !v.trim().is_empty() => Auth::parse(&v),
+            _ => bail!("API_KEYS is not set, set it or run with API_AUTH_DISABLED=true")
+        }
+    }
+
+    fn parse(v: &str) -> Result<Self> {
+        let mut keys = vec![];
+
+        for entry in v.split(',').map(|e| e.trim()).filter(|e| !e.is_empty()) {
+            let (name, key) = match entry.split_once(':') {
+                Some((name, key)) if !name.trim().is_empty() && !key.trim().is_empty() => (name.trim(), key.trim()),
+                _ => bail!("Invalid API_KEYS entry, expected name:key")
+            };
+
+            if keys.iter().any(|k: &ApiKey| k.name == name) {
+                bail!("Duplicate API_KEYS name: {}", name);
+            }
+
+            keys.push(ApiKey { name: name.to_string(), key: key.to_string() });
+        }
+
+        if keys.is_empty() {
+            bail!("API_KEYS doesn't contain any keys");
+        }
+
+        Ok(Auth::ApiKeys(keys))
+    }
+
+    /// Accepts `Authorization: Bearer <key>` or `X-Api-Key: <key>`.
+    /// Returns the name of the matching key, `None` when auth is disabled.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Option<String>, Unauthorized> {
+        let keys = match self {
+            Auth::Disabled => return Ok(None),
+            Auth::ApiKeys(keys) => keys
+        };
+
+        let provided = headers.get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("bearer ")))
+            .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+            .map(|v| v.trim())
+            .ok_or(Unauthorized)?;
+
+        // every key is compared so the response time doesn't tell which one was close
+        let mut matched = None;
+        for k in keys {
+            if constant_time_eq(k.key.as_bytes(), provided.as_bytes()) {
+                matched = Some(k.name.clone());
+            }
+        }
+
+        matched.map(Some).ok_or(Unauthorized)
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: &str, value: &str) -> HeaderMap {
+        let mut h = HeaderMap::new();
+        h.insert(lambda_http::http::HeaderName::from_bytes(name.as_bytes()).unwrap(), value.parse().unwrap());
+        h
+    }
+
+    #[test]
+    fn parses_keys() {
+        let auth = Auth::parse(" gpt:abc , phone:def:ghi ").unwrap();
+        assert_eq!(auth, Auth::ApiKeys(vec![
+            ApiKey { name: "gpt".to_string(), key: "abc".to_string() },
+            ApiKey { name: "phone".to_string(), key: "def:ghi".to_string() }
+        ]));
+
+        assert!(Auth::parse("abc").is_err());
+        assert!(Auth::parse("gpt:").is_err());
+        assert!(Auth::parse("gpt:a,gpt:b").is_err());
+        assert!(Auth::parse(" , ").is_err());
+    }
+
+    #[test]
+    fn authenticates_bearer_and_api_key_headers() {
+        let auth = Auth::parse("gpt:abc,phone:def").unwrap();
+
+        assert_eq!(auth.authenticate(&headers("authorization", "Bearer abc")).unwrap().as_deref(), Some("gpt"));
+        assert_eq!(auth.authenticate(&headers("x-api-key", "def")).unwrap().as_deref(), Some("phone"));
+
+        assert!(auth.authenticate(&HeaderMap::new()).is_err());
+        assert!(auth.authenticate(&headers("authorization", "Bearer abd")).is_err());
+        assert!(auth.authenticate(&headers("authorization", "Basic abc")).is_err());
+        assert!(auth.authenticate(&headers("x-api-key", "ab")).is_err());
+    }
+
+    #[test]
+    fn disabled_accepts_anything() {
+        assert_eq!(Auth::Disabled.authenticate(&HeaderMap::new()).unwrap(), None);
+    }
+}
@@ -0,0 +1,46 @@
+use lambda_http::{Body, Response};
+use serde::Serialize;
+use data::models::responses::{ErrorCode, ErrorRes};
+
+pub fn json_response<T: Serialize>(status: u16, body: &T) -> Result<Response<Body>, lambda_http::Error> {
+    let resp = Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(serde_json::to_string(body)?.into())
+        .map_err(Box::new)?;
+
+    Ok(resp)
+}
+
+pub fn empty_response(status: u16) -> Result<Response<Body>, lambda_http::Error> {
+    let resp = Response::builder()
+        .status(status)
+        .body(Body::Empty)
+        .map_err(Box::new)?;
+
+    Ok(resp)
+}
+
+/// `ErrorRes` body with the status matching the code
+pub fn error_response(code: ErrorCode, message: &str) -> Result<Response<Body>, lambda_http::Error> {
+    json_response(error_status(code), &ErrorRes::new(code, message))
+}
+
+pub fn unauthorized_response() -> Result<Response<Body>, lambda_http::Error> {
+    let mut resp = error_response(ErrorCode::Unauthorized, "Missing or invalid api key")?;
+    resp.headers_mut().insert("www-authenticate", "Bearer".parse()?);
+
+    Ok(resp)
+}
+
+pub fn error_status(code: ErrorCode) -> u16 {
+    match code {
+        ErrorCode::BadRequest => 400,
+        ErrorCode::Unauthorized => 401,
+        ErrorCode::NotFound => 404,
+        ErrorCode::MethodNotAllowed => 405,
+        ErrorCode::Conflict => 409,
+        ErrorCode::LlmFailed => 502,
+        ErrorCode::Internal => 500
+    }
+}
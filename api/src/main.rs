@@ -1,14 +1,48 @@
+mod server;
+mod http;
+mod words;
+mod auth;
+mod openapi;
+
 use std::str::FromStr;
+use std::collections::HashSet;
+use std::sync::Arc;
 
 use lambda_http::{run, service_fn, Body, Request, Response, RequestExt};
-use anyhow::{Result, anyhow};
+use anyhow::Result;
 use dotenv::dotenv;
+use serde::Deserialize;
 use serde_json::Value;
+use utoipa::ToSchema;
 use data::{
-    models::{requests::{NihongoWordReqChatgpt, NihongoWordReq}, oai::{Prompt, ModelProvider, NihongoWordOpenAiRes}, db::NihongoWordInsert},
-    services::data::{add_word, add_word_tense}
+    models::{
+        requests::{NihongoWordReqChatgpt, NihongoWordReqWordChatgpt, NihongoWordReq, NihongoSentenceReq},
+        oai::{Prompt, NihongoWordOpenAiRes, NihongoSentenceWordsOpenAiRes},
+        db::{NihongoWordInsert, NihongoWordInsertOutcome, NihongoWordSource, ConflictPolicy},
+        responses::{NihongoWordSaveRes, NihongoWordSaveResult, NihongoWordSaveStatus, ErrorCode, ErrorRes}
+    },
+    services::store::{self, WordStore},
+    validation::validate_word
 };
-use services::oai::ChatAsync;
+use services::{oai::ChatAsync, llm::LlmClient};
+use http::{json_response, error_response, unauthorized_response};
+use auth::Auth;
+use openapi::SaveWordsReq;
+
+struct AppState {
+    db: Arc<dyn WordStore>,
+    llm: Arc<dyn LlmClient>,
+    auth: Auth,
+    /// Used when a save request doesn't set `on_conflict`
+    on_conflict: ConflictPolicy
+}
+
+/// Per request settings for saving words
+#[derive(Debug, Clone, Copy)]
+struct SaveOptions<'a> {
+    created_by: Option<&'a str>,
+    on_conflict: ConflictPolicy
+}
 
 #[tokio::main]
 async fn main() -> Result<(), lambda_http::Error> {
@@ -19,134 +53,298 @@ async fn main() -> Result<(), lambda_http::Error> {
         .with_target(false)
         .init();
 
-    run(service_fn(function_handler)).await
-}
+    let db = store::from_env().await?;
+    let command = std::env::args().nth(1);
 
-async fn function_handler(event: Request) -> Result<Response<Body>, lambda_http::Error> {
-    match event.method().as_str() {
-        "POST" => {
-            let resp = post_handler(event).await?;
-            Ok(resp)
+    // `rust-lambda-nihongo migrate` applies the migrations and exits
+    if command.as_deref() == Some("migrate") {
+        db.migrate().await?;
+        tracing::info!("Migrations applied");
+        return Ok(());
+    }
+
+    if store::run_migrations_on_startup() {
+        db.migrate().await?;
+    }
+
+    let state = Arc::new(AppState {
+        db,
+        llm: services::llm::from_env()?,
+        auth: Auth::from_env()?,
+        on_conflict: match std::env::var("WORD_CONFLICT_POLICY") {
+            Ok(v) => v.parse()?,
+            Err(_) => ConflictPolicy::default()
         }
-        _ => {
-            let resp = Response::builder()
-                .status(405)
-                .header("content-type", "text/plain")
-                .body("Method Not Allowed".into())
-                .map_err(Box::new)?;
-            Ok(resp)
+    });
+
+    // `rust-lambda-nihongo serve` runs a regular http server instead of the lambda runtime
+    if command.as_deref() == Some("serve") {
+        server::serve(state).await?;
+        return Ok(());
+    }
+
+    let state = &state;
+    run(service_fn(move |event| async move { function_handler(state, event).await })).await
+}
+
+async fn function_handler(state: &AppState, event: Request) -> Result<Response<Body>, lambda_http::Error> {
+    let method = event.method().clone();
+    let path = event.uri().path().to_string();
+
+    match route(state, event).await {
+        Ok(resp) => Ok(resp),
+        Err(e) => {
+            tracing::error!("Request failed | method: {} | path: {} | error: {}", method, path, e);
+            error_response(ErrorCode::Internal, "Internal server error")
         }
     }
 }
 
-async fn post_handler(event: Request) -> Result<Response<Body>, lambda_http::Error> {
-    let body = event.body();
-    let params = event.query_string_parameters();
-
-    let req_type_param = match params.all("req_type") {
-        Some(v) => v,
-        None => {
-            let resp = Response::builder()
-                .status(400)
-                .header("content-type", "text/plain")
-                .body("Invalid or missing req_type".into())
-                .map_err(Box::new)?;
-
-            return Ok(resp);
+async fn route(state: &AppState, event: Request) -> Result<Response<Body>, lambda_http::Error> {
+    let path = event.uri().path().to_string();
+    let segments = path.split('/').filter(|s| !s.is_empty()).collect::<Vec<&str>>();
+
+    // the GPT action importer fetches the spec without a key
+    if let ("GET", [prefix @ .., "openapi.json"]) = (event.method().as_str(), segments.as_slice()) {
+        return openapi::handle_openapi(&event, prefix);
+    }
+
+    let created_by = match state.auth.authenticate(event.headers()) {
+        Ok(name) => name,
+        Err(_) => {
+            tracing::warn!("Unauthorized request | method: {} | path: {}", event.method(), path);
+            return unauthorized_response();
         }
     };
 
-    let req_type: PostWordType = match req_type_param.first().ok_or_else(|| anyhow!("Missing req_type"))?.parse() {
-        Ok(v) => v,
-        Err(_) => {
-            let resp = Response::builder()
-                .status(400)
-                .header("content-type", "text/plain")
-                .body("Invalid or missing req_type".into())
-                .map_err(Box::new)?;
+    // matched on the tail so the api gateway stage prefix doesn't matter
+    match (event.method().as_str(), segments.as_slice()) {
+        ("POST", [.., "api", "words", "batch"]) => save_words(state, &event, created_by.as_deref(), PostWordType::ChatGpt).await,
+        ("POST", [.., "api", "words", "lookup"]) => save_words(state, &event, created_by.as_deref(), PostWordType::SingularWord).await,
+        ("POST", [.., "api", "sentences"]) => save_words(state, &event, created_by.as_deref(), PostWordType::Sentence).await,
+        ("POST", [.., "api", "words", id, "sentences"]) => words::handle_add_sentence(state, id, event.body()).await,
+        ("POST", [.., "api", "words"]) => post_handler(state, &event, created_by.as_deref()).await,
+        (_, [.., "api", "words", "batch" | "lookup"] | [.., "api", "sentences"]) => error_response(ErrorCode::MethodNotAllowed, "Method not allowed"),
+        ("GET", [.., "api", "words"]) => words::handle_list_words(state, &event.query_string_parameters()).await,
+        ("GET", [.., "api", "words", id]) => words::handle_get_word(state, id).await,
+        ("PUT" | "PATCH", [.., "api", "words", id]) => words::handle_update_word(state, id, event.body()).await,
+        ("DELETE", [.., "api", "words", id]) => words::handle_delete_word(state, id).await,
+        ("PUT" | "PATCH", [.., "api", "words", id, "tenses", tense_id]) => words::handle_update_tense(state, id, tense_id, event.body()).await,
+        ("DELETE", [.., "api", "words", id, "tenses", tense_id]) => words::handle_delete_tense(state, id, tense_id).await,
+        ("GET" | "POST", _) => error_response(ErrorCode::NotFound, "Not found"),
+        _ => error_response(ErrorCode::MethodNotAllowed, "Method not allowed")
+    }
+}
 
-            return Ok(resp);
-        }
+/// `POST /api/words?req_type=`, kept for GPT actions configured before the save routes had their own paths
+#[utoipa::path(
+    post,
+    path = "/api/words",
+    operation_id = "ProcessNihongoSentenceWords",
+    tag = "words",
+    description = "Use `/api/words/batch`, `/api/words/lookup` or `/api/sentences` instead. `req_type` decides the body: \
+        `chatgpt` takes fully built words, `singular_word` a single word the LLM fills in, and `sentence` a sentence the LLM splits into vocab words",
+    params(
+        ("req_type" = PostWordType, Query),
+        ("on_conflict" = Option<ConflictPolicy>, Query, description = "What happens to words that are already saved, defaults to WORD_CONFLICT_POLICY")
+    ),
+    request_body(content = inline(SaveWordsReq), description = "Depends on `req_type`: the `/api/words/batch`, `/api/words/lookup` or `/api/sentences` body"),
+    responses(
+        (status = 200, description = "Outcome of every submitted word", body = NihongoWordSaveRes),
+        (status = 400, description = "Invalid request", body = ErrorRes),
+        (status = 401, description = "Missing or invalid api key", body = ErrorRes),
+        (status = 502, description = "The sentence couldn't be split into words", body = ErrorRes)
+    )
+)]
+async fn post_handler(state: &AppState, event: &Request, created_by: Option<&str>) -> Result<Response<Body>, lambda_http::Error> {
+    let req_type: PostWordType = match event.query_string_parameters().first("req_type").map(|v| v.parse()) {
+        Some(Ok(v)) => v,
+        _ => return error_response(ErrorCode::BadRequest, "Invalid or missing req_type, expected chatgpt, singular_word or sentence")
+    };
+
+    save_words(state, event, created_by, req_type).await
+}
+
+/// Reads the options every save route shares and hands the body to the handler for `req_type`
+async fn save_words(state: &AppState, event: &Request, created_by: Option<&str>, req_type: PostWordType) -> Result<Response<Body>, lambda_http::Error> {
+    let body = event.body();
+
+    let on_conflict = match event.query_string_parameters().first("on_conflict").map(|v| v.parse::<ConflictPolicy>()) {
+        Some(Ok(p)) => p,
+        Some(Err(e)) => return error_response(ErrorCode::BadRequest, &e.to_string()),
+        None => state.on_conflict
     };
+    let opts = SaveOptions { created_by, on_conflict };
 
     match req_type {
-        PostWordType::ChatGpt => handle_chatgpt_req(body).await,
-        PostWordType::SingularWord => handle_singular_req(body).await
+        PostWordType::ChatGpt => handle_chatgpt_req(state, body, opts).await,
+        PostWordType::SingularWord => handle_singular_req(state, body, opts).await,
+        PostWordType::Sentence => handle_sentence_req(state, body, opts).await
     }
 }
 
-async fn handle_chatgpt_req(body: &Body) -> Result<Response<Body>, lambda_http::Error> {
-    match serde_json::from_slice::<NihongoWordReqChatgpt>(body.as_ref()) {
-        Ok(b) => {
-            println!("Body: {:?}", b);
-            for w in &b.words {
-                add_to_table(w.clone().into()).await?;
+/// Words are deserialized one at a time so a single malformed word doesn't reject the rest of the batch
+#[derive(Debug, Deserialize)]
+struct ChatgptBatch {
+    words: Vec<Value>,
+    source: Option<NihongoWordSource>
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/words/batch",
+    operation_id = "SaveWords",
+    tag = "words",
+    description = "Saves fully built words, e.g. the ones the GPT wrote",
+    params(
+        ("on_conflict" = Option<ConflictPolicy>, Query, description = "What happens to words that are already saved, defaults to WORD_CONFLICT_POLICY")
+    ),
+    request_body = NihongoWordReqChatgpt,
+    responses(
+        (status = 200, description = "Outcome of every submitted word", body = NihongoWordSaveRes),
+        (status = 400, description = "Invalid body", body = ErrorRes)
+    )
+)]
+async fn handle_chatgpt_req(state: &AppState, body: &Body, opts: SaveOptions<'_>) -> Result<Response<Body>, lambda_http::Error> {
+    let batch = match serde_json::from_slice::<ChatgptBatch>(body.as_ref()) {
+        Ok(b) => b,
+        Err(e) => return invalid_body_response(body, e)
+    };
+
+    let mut results = vec![];
+    for w in batch.words {
+        let result = match serde_json::from_value::<NihongoWordReqWordChatgpt>(w.clone()) {
+            Ok(mut word) => {
+                if word.source.is_none() {
+                    word.source = batch.source.clone();
+                }
+                add_to_table(state.db.as_ref(), word.into(), opts).await
+            },
+            Err(e) => NihongoWordSaveResult {
+                word: w["word"].as_str().unwrap_or_default().to_string(),
+                status: NihongoWordSaveStatus::Invalid,
+                id: None,
+                reason: Some(e.to_string()),
+                warnings: vec![]
             }
+        };
+        results.push(result);
+    }
+
+    json_response(200, &NihongoWordSaveRes { results })
+}
 
-            let resp = Response::builder()
-                .status(200)
-                .header("content-type", "text/plain")
-                .body("".into())
-                .map_err(Box::new)?;
+#[utoipa::path(
+    post,
+    path = "/api/words/lookup",
+    operation_id = "LookupWord",
+    tag = "words",
+    description = "Saves a single word, the LLM fills in the definition, sentence, mnemonics and tenses",
+    params(
+        ("on_conflict" = Option<ConflictPolicy>, Query, description = "What happens to words that are already saved, defaults to WORD_CONFLICT_POLICY")
+    ),
+    request_body = NihongoWordReq,
+    responses(
+        (status = 200, description = "Outcome of the word", body = NihongoWordSaveRes),
+        (status = 400, description = "Invalid body", body = ErrorRes)
+    )
+)]
+async fn handle_singular_req(state: &AppState, body: &Body, opts: SaveOptions<'_>) -> Result<Response<Body>, lambda_http::Error> {
+    let w = match serde_json::from_slice::<NihongoWordReq>(body.as_ref()) {
+        Ok(w) => w,
+        Err(e) => return invalid_body_response(body, e)
+    };
 
-            Ok(resp)
-        },
-        Err(e) => {
-            let body: Value = serde_json::from_slice(body.as_ref())?;
-            println!("Failed to deserialize error: {} | request body: {}", e, body);
+    let result = generate_and_add(state, &w.word, w.source.as_ref(), opts).await;
+
+    json_response(200, &NihongoWordSaveRes { results: vec![result] })
+}
 
-            let resp = Response::builder()
-                .status(400)
-                .header("content-type", "text/plain")
-                .body("".into())
-                .map_err(Box::new)?;
+#[utoipa::path(
+    post,
+    path = "/api/sentences",
+    operation_id = "SaveSentenceWords",
+    tag = "words",
+    description = "Splits a sentence into its base form vocab words (particles excluded) and saves each one like `/api/words/lookup`",
+    params(
+        ("on_conflict" = Option<ConflictPolicy>, Query, description = "What happens to words that are already saved, defaults to WORD_CONFLICT_POLICY")
+    ),
+    request_body = NihongoSentenceReq,
+    responses(
+        (status = 200, description = "Outcome of every word extracted from the sentence", body = NihongoWordSaveRes),
+        (status = 400, description = "Invalid body", body = ErrorRes),
+        (status = 502, description = "The sentence couldn't be split into words", body = ErrorRes)
+    )
+)]
+async fn handle_sentence_req(state: &AppState, body: &Body, opts: SaveOptions<'_>) -> Result<Response<Body>, lambda_http::Error> {
+    let s = match serde_json::from_slice::<NihongoSentenceReq>(body.as_ref()) {
+        Ok(s) => s,
+        Err(e) => return invalid_body_response(body, e)
+    };
 
-            Ok(resp)
+    let prompt = Prompt {
+        system_prompt: get_sentence_system_prompt(),
+        user_prompt: get_sentence_user_prompt(&s.sentence)
+    };
+
+    let mut res: NihongoSentenceWordsOpenAiRes = match ChatAsync::new(state.llm.clone(), prompt).chat_json().await {
+        Ok(res) => res,
+        Err(e) => {
+            tracing::error!("Failed to extract words | sentence: {} | error: {}", s.sentence, e);
+            return error_response(ErrorCode::LlmFailed, "Failed to extract the words from the sentence");
         }
-    }
-}
+    };
 
-async fn handle_singular_req(body: &Body) -> Result<Response<Body>, lambda_http::Error> {
-    match serde_json::from_slice::<NihongoWordReq>(body.as_ref()) {
-        Ok(w) => {
-            let system_prompt = get_system_prompt();
-            let user_prompt = get_user_prompt(&w.word);
+    // the model occasionally repeats a word that shows up twice in the sentence
+    let mut seen = HashSet::new();
+    res.words.retain(|w| !w.trim().is_empty() && seen.insert(w.trim().to_string()));
 
-            let prompt = Prompt {
-                system_prompt,
-                user_prompt,
-                model: "mistral-medium".to_string(),
-                provider: ModelProvider::Mistral
-            };
+    tracing::info!("Sentence: {} | extracted words: {:?}", s.sentence, res.words);
+    let mut results = vec![];
+    for word in &res.words {
+        results.push(generate_and_add(state, word, s.source.as_ref(), opts).await);
+    }
 
-            let res: NihongoWordOpenAiRes = ChatAsync::new(prompt).chat_json().await?;
-            add_to_table(res.into()).await?;
+    json_response(200, &NihongoWordSaveRes { results })
+}
 
-            let resp = Response::builder()
-                .status(200)
-                .header("content-type", "text/plain")
-                .body("".into())
-                .map_err(Box::new)?;
+fn invalid_body_response(body: &Body, e: serde_json::Error) -> Result<Response<Body>, lambda_http::Error> {
+    tracing::info!("Failed to deserialize error: {} | request body: {}", e, String::from_utf8_lossy(body.as_ref()));
+    error_response(ErrorCode::BadRequest, &format!("Invalid request body: {}", e))
+}
 
-            Ok(resp)
+/// LLM failures are reported for the word rather than failing the request, the other words still get saved
+async fn generate_and_add(state: &AppState, word: &str, source: Option<&NihongoWordSource>, opts: SaveOptions<'_>) -> NihongoWordSaveResult {
+    match generate_word(state, word).await {
+        Ok(res) => {
+            let mut insert: NihongoWordInsert = res.into();
+            insert.source = source.cloned().unwrap_or_default();
+            add_to_table(state.db.as_ref(), insert, opts).await
         },
         Err(e) => {
-            let body: Value = serde_json::from_slice(body.as_ref())?;
-            println!("Failed to deserialize error: {} | request body: {}", e, body);
-
-            let resp = Response::builder()
-                .status(400)
-                .header("content-type", "text/plain")
-                .body("".into())
-                .map_err(Box::new)?;
-
-            Ok(resp)
+            tracing::error!("Failed to generate word: {} | error: {}", word, e);
+            NihongoWordSaveResult {
+                word: word.to_string(),
+                status: NihongoWordSaveStatus::Failed,
+                id: None,
+                reason: Some("The LLM didn't return a valid word after retrying".to_string()),
+                warnings: vec![]
+            }
         }
     }
 }
 
+async fn generate_word(state: &AppState, word: &str) -> Result<NihongoWordOpenAiRes> {
+    let prompt = Prompt {
+        system_prompt: get_system_prompt(),
+        user_prompt: get_user_prompt(word)
+    };
+
+    ChatAsync::new(state.llm.clone(), prompt).chat_json().await
+}
+
 fn get_system_prompt() -> String {
-    format!("
+    "
         YOU RESPOND WITH JSON ONLY NO OTHER WORDS AT ALL BESIDES FOR JSON.
         You are a tool to help users learn Japanese.
 
@@ -163,7 +361,7 @@ fn get_system_prompt() -> String {
         If word tenses are not needed, return an empty array.
         
         Please respond with the below JSON only, NO OTHER WORDS EXCEPT THIS JSON:
-        {{
+        {
           'word': <String>,
           'is_kanji': <Boolean>,
           'word_reading': <String>,
@@ -173,15 +371,15 @@ fn get_system_prompt() -> String {
           'kanji_mnemonic': <String>,
           'spoken_mnemonic': <String>,
           'word_tenses': [
-            {{
+            {
               'word': <String>,
               'sentence': <String>,
               'tense_type': <String>
-            }}
+            }
             ...
           ]
-        }}
-    ")
+        }
+    ".to_string()
 }
 
 fn get_user_prompt(word: &str) -> String {
@@ -190,24 +388,81 @@ fn get_user_prompt(word: &str) -> String {
     ",  word)
 }
 
-async fn add_to_table(word: NihongoWordInsert) -> Result<()> {
-    match add_word(&word).await? {
-        Some(id) => {
-            add_word_tense(id, word.word_tenses).await?;
+fn get_sentence_system_prompt() -> String {
+    "
+        YOU RESPOND WITH JSON ONLY NO OTHER WORDS AT ALL BESIDES FOR JSON.
+        You are a tool to help users learn Japanese.
+
+        You will be provided with a Japanese sentence, your job is to list every vocab word in it:
+
+        1. Break the sentence down into its individual vocab words
+        2. Exclude particles (e.g. は, が, を, に, で, と, も, の, へ, から, まで, よ, ね)
+        3. Return every word in its base (dictionary) form, no conjugated forms
+        4. Keep the word written the way it appears in the sentence (use kanji if the sentence uses kanji)
+        5. List each word only once
+
+        Please respond with the below JSON only, NO OTHER WORDS EXCEPT THIS JSON:
+        {
+          'words': [<String>, ...]
+        }
+    ".to_string()
+}
+
+fn get_sentence_user_prompt(sentence: &str) -> String {
+    format!("
+        Sentence: {}
+    ",  sentence)
+}
+
+/// Validates the word and saves it, words that fail validation are reported as invalid rather than saved
+async fn add_to_table(db: &dyn WordStore, mut word: NihongoWordInsert, opts: SaveOptions<'_>) -> NihongoWordSaveResult {
+    word.created_by = opts.created_by.map(|c| c.to_string());
+
+    let warnings = match validate_word(&mut word) {
+        Ok(warnings) => warnings,
+        Err(e) => {
+            tracing::info!("Invalid word: {} | problems: {}", word.word, e);
+            return NihongoWordSaveResult {
+                word: word.word,
+                status: NihongoWordSaveStatus::Invalid,
+                id: None,
+                reason: Some(e.to_string()),
+                warnings: vec![]
+            };
+        }
+    };
+
+    let (status, id, reason) = match db.insert_word_with_tenses(&word, opts.on_conflict).await {
+        Ok(NihongoWordInsertOutcome::Created(id)) => {
+            tracing::info!("Saved word: {} | id: {} | tenses: {}", word.word, id, word.word_tenses.len());
+            (NihongoWordSaveStatus::Created, Some(id), None)
+        },
+        Ok(NihongoWordInsertOutcome::Duplicate(id)) => {
+            tracing::info!("Word: {} already exists in DB", word.word);
+            (NihongoWordSaveStatus::Duplicate, Some(id), None)
+        },
+        Ok(NihongoWordInsertOutcome::Updated(id)) => {
+            tracing::info!("Updated existing word: {} | id: {} | on conflict: {:?}", word.word, id, opts.on_conflict);
+            (NihongoWordSaveStatus::Updated, Some(id), None)
         },
-        None => {
-            println!("Word: {} already exists in DB", word.word);
+        Err(e) => {
+            tracing::error!("Failed to save word: {} | error: {}", word.word, e);
+            (NihongoWordSaveStatus::Failed, None, Some("Failed to save the word".to_string()))
         }
     };
 
-    Ok(())
+    NihongoWordSaveResult { word: word.word, status, id, reason, warnings }
 }
 
 
-#[derive(Debug)]
+#[derive(Debug, ToSchema)]
 enum PostWordType {
+    #[schema(rename = "chatgpt")]
     ChatGpt,
-    SingularWord
+    #[schema(rename = "singular_word")]
+    SingularWord,
+    #[schema(rename = "sentence")]
+    Sentence
 }
 
 
@@ -221,7 +476,447 @@ impl FromStr for PostWordType {
         match s {
             "chatgpt" => Ok(PostWordType::ChatGpt),
             "singular_word" => Ok(PostWordType::SingularWord),
+            "sentence" => Ok(PostWordType::Sentence),
             _ => Err(ParseWordTypeError)
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use data::models::responses::ErrorRes;
+    use services::llm::{ChatMessage, mock::MockLlmClient};
+
+    const FIXTURE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/llm");
+
+    async fn state(llm: Arc<dyn LlmClient>) -> AppState {
+        let db = store::connect("sqlite::memory:", 1).await.unwrap();
+        db.migrate().await.unwrap();
+
+        AppState { db, llm, auth: Auth::Disabled, on_conflict: ConflictPolicy::Skip }
+    }
+
+    /// The recorded 食べる response, as the chatgpt action would post it
+    fn fixture_word() -> Value {
+        serde_json::from_str(&std::fs::read_to_string(
+            format!("{}/edd1c068d19f8b7a595fd05e92014f3c562fdec33299a6eaf82433f1b7133c8c.json", FIXTURE_DIR)
+        ).unwrap()).unwrap()
+    }
+
+    fn opts() -> SaveOptions<'static> {
+        SaveOptions { created_by: None, on_conflict: ConflictPolicy::Skip }
+    }
+
+    #[tokio::test]
+    async fn generate_word_replays_recorded_response() {
+        let llm = Arc::new(MockLlmClient::from_fixture_dir(FIXTURE_DIR).unwrap());
+        let state = state(llm.clone()).await;
+
+        let res = generate_word(&state, "食べる").await.unwrap();
+        assert_eq!(res.word_reading, "たべる");
+        assert_eq!(res.word_tenses.len(), 2);
+
+        let calls = llm.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0], vec![ChatMessage::system(get_system_prompt()), ChatMessage::user(get_user_prompt("食べる"))]);
+    }
+
+    #[tokio::test]
+    async fn generate_word_fails_without_fixture() {
+        let llm = Arc::new(MockLlmClient::from_fixture_dir(FIXTURE_DIR).unwrap());
+        let state = state(llm.clone()).await;
+
+        assert!(generate_word(&state, "飲む").await.is_err());
+        assert_eq!(llm.calls().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn singular_req_generates_and_saves_word() {
+        let llm = Arc::new(MockLlmClient::from_fixture_dir(FIXTURE_DIR).unwrap());
+        let state = state(llm.clone()).await;
+
+        let body = Body::from(r#"{ "word": "食べる" }"#);
+        let resp = handle_singular_req(&state, &body, opts()).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(res.results[0].status, NihongoWordSaveStatus::Created);
+
+        let rows = state.db.get_unprocessed_words().await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|r| r.word == "食べる" && r.word_reading == "たべる"));
+
+        // saving the same word again is a no-op
+        let resp = handle_singular_req(&state, &body, opts()).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(res.results[0].status, NihongoWordSaveStatus::Duplicate);
+        assert_eq!(state.db.get_unprocessed_words().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn singular_req_rejects_invalid_body() {
+        let llm = Arc::new(MockLlmClient::new());
+        let state = state(llm.clone()).await;
+
+        let body = Body::from(r#"{ "words": [] }"#);
+        let resp = handle_singular_req(&state, &body, opts()).await.unwrap();
+
+        assert_eq!(resp.status(), 400);
+        let err: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(err["error"]["code"], "bad_request");
+        assert!(llm.calls().is_empty());
+
+        // not even json
+        let resp = handle_singular_req(&state, &Body::from("食べる"), opts()).await.unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn singular_req_reports_llm_failure() {
+        let llm = Arc::new(MockLlmClient::new());
+        let state = state(llm.clone()).await;
+
+        let resp = handle_singular_req(&state, &Body::from(r#"{ "word": "飲む" }"#), opts()).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(res.results[0].status, NihongoWordSaveStatus::Failed);
+        assert!(res.results[0].reason.is_some());
+    }
+
+    #[tokio::test]
+    async fn chatgpt_req_reports_each_word() {
+        let state = state(Arc::new(MockLlmClient::new())).await;
+        let word = fixture_word();
+
+        let body = Body::from(format!(r#"{{ "words": [{}, {{ "word": "飲む" }}, {}] }}"#, word, word));
+        let resp = handle_chatgpt_req(&state, &body, opts()).await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        let statuses = res.results.iter().map(|r| (r.word.as_str(), r.status)).collect::<Vec<(&str, NihongoWordSaveStatus)>>();
+        assert_eq!(statuses, vec![
+            ("食べる", NihongoWordSaveStatus::Created),
+            ("飲む", NihongoWordSaveStatus::Invalid),
+            ("食べる", NihongoWordSaveStatus::Duplicate)
+        ]);
+        assert!(res.results[0].id.is_some());
+        assert!(res.results[1].reason.as_deref().unwrap().contains("missing field"));
+
+        let resp = handle_chatgpt_req(&state, &Body::from(r#"{ "word": "食べる" }"#), opts()).await.unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn chatgpt_req_validates_words() {
+        let state = state(Arc::new(MockLlmClient::new())).await;
+        let word = fixture_word();
+        let mut not_kanji = word.clone();
+        not_kanji["is_kanji"] = Value::from(false);
+        let mut romaji = word.clone();
+        romaji["word"] = Value::from("飲む");
+        romaji["word_reading"] = Value::from("nomu");
+
+        let body = serde_json::json!({ "words": [not_kanji, romaji] });
+        let resp = handle_chatgpt_req(&state, &Body::from(body.to_string()), opts()).await.unwrap();
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+
+        assert_eq!(res.results[0].status, NihongoWordSaveStatus::Created);
+        assert!(res.results[0].warnings.contains(&"is_kanji corrected to true".to_string()));
+        assert!(state.db.get_word(res.results[0].id.unwrap()).await.unwrap().unwrap().is_kanji);
+
+        assert_eq!(res.results[1].status, NihongoWordSaveStatus::Invalid);
+        assert_eq!(res.results[1].reason.as_deref(), Some("word_reading must be hiragana or katakana, got nomu"));
+    }
+
+    #[tokio::test]
+    async fn chatgpt_req_saves_batch_and_word_sources() {
+        let state = state(Arc::new(MockLlmClient::new())).await;
+        let word = fixture_word();
+        let mut own_source = word.clone();
+        own_source["word"] = Value::from("飲む");
+        own_source["source"] = serde_json::json!({ "title": "NHK Easy", "url": "https://www3.nhk.or.jp/news/easy/" });
+
+        let body = serde_json::json!({
+            "words": [word, own_source],
+            "source": { "title": "よつばと！", "location": "Vol. 1 ch. 3", "seen_at": "2024-04-01T09:30:00Z" }
+        });
+        let resp = handle_chatgpt_req(&state, &Body::from(body.to_string()), opts()).await.unwrap();
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+
+        let batch = state.db.get_word(res.results[0].id.unwrap()).await.unwrap().unwrap().source.unwrap();
+        assert_eq!(batch.title.as_deref(), Some("よつばと！"));
+        assert_eq!(batch.location.as_deref(), Some("Vol. 1 ch. 3"));
+        assert_eq!(batch.seen_at, Some("2024-04-01T09:30:00Z".parse().unwrap()));
+
+        let own = state.db.get_word(res.results[1].id.unwrap()).await.unwrap().unwrap().source.unwrap();
+        assert_eq!(own.title.as_deref(), Some("NHK Easy"));
+        assert_eq!(own.location, None);
+    }
+
+    #[tokio::test]
+    async fn routes_read_endpoints() {
+        let state = state(Arc::new(MockLlmClient::new())).await;
+        let word = serde_json::from_value::<NihongoWordOpenAiRes>(fixture_word()).unwrap();
+        add_to_table(state.db.as_ref(), word.into(), opts()).await;
+
+        let get = |uri: &str| lambda_http::http::Request::get(uri).body(Body::Empty).unwrap();
+
+        let resp = function_handler(&state, get("/default/api/words/")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let list: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(list["total"], 1);
+        let id = list["words"][0]["id"].as_i64().unwrap();
+
+        let resp = function_handler(&state, get(&format!("/api/words/{}", id))).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(word["word"], "食べる");
+        assert_eq!(word["word_tenses"].as_array().unwrap().len(), 2);
+
+        let resp = function_handler(&state, get(&format!("/api/words/{}", id + 1))).await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        let resp = function_handler(&state, get("/api/nope")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+        let err: ErrorRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(err.error.code, ErrorCode::NotFound);
+
+        let delete = lambda_http::http::Request::delete("/api/words").body(Body::Empty).unwrap();
+        assert_eq!(function_handler(&state, delete).await.unwrap().status(), 405);
+    }
+
+    #[tokio::test]
+    async fn routes_update_and_delete_endpoints() {
+        let state = state(Arc::new(MockLlmClient::new())).await;
+        let word = serde_json::from_value::<NihongoWordOpenAiRes>(fixture_word()).unwrap();
+        add_to_table(state.db.as_ref(), word.into(), opts()).await;
+
+        let (words, _) = state.db.list_words(&Default::default()).await.unwrap();
+        let (id, tense_id) = (words[0].id, words[0].word_tenses[0].id);
+        state.db.update_word_status(id).await.unwrap();
+
+        let req = |method: &str, uri: &str, body: &str| lambda_http::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(Body::from(body))
+            .unwrap();
+
+        let resp = function_handler(&state, req("PATCH", &format!("/api/words/{}", id), r#"{ "definition": "to eat; to consume" }"#)).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(word["definition"], "to eat; to consume");
+        assert_eq!(word["is_processed"], false);
+        assert!(word["kanji_mnemonic"].is_string());
+
+        let resp = function_handler(&state, req("PATCH", &format!("/api/words/{}", id), r#"{ "kanji_mnemonic": null }"#)).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert!(word["kanji_mnemonic"].is_null());
+        assert_eq!(word["spoken_mnemonic"], "The TABBY cat RUns to eat");
+
+        let resp = function_handler(&state, req("PATCH", &format!("/api/words/{}", id), r#"{ "word_reading": "taberu", "definition": " " }"#)).await.unwrap();
+        assert_eq!(resp.status(), 400);
+        let err: ErrorRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(err.error.message, "Invalid word: definition must not be empty; word_reading must be hiragana or katakana, got taberu");
+        assert_eq!(state.db.get_word(id).await.unwrap().unwrap().word_reading, "たべる");
+
+        let resp = function_handler(&state, req("PATCH", &format!("/api/words/{}", id), r#"{ "word": " たべる " }"#)).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(word["word"], "たべる");
+        assert_eq!(word["is_kanji"], false);
+        let resp = function_handler(&state, req("PATCH", &format!("/api/words/{}", id), r#"{ "word": "食べる" }"#)).await.unwrap();
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(word["is_kanji"], true);
+
+        let resp = function_handler(&state, req("PUT", &format!("/api/words/{}", id), r#"{ "unknown": 1 }"#)).await.unwrap();
+        assert_eq!(resp.status(), 400);
+        let resp = function_handler(&state, req("PATCH", &format!("/api/words/{}", id + 1), "{}")).await.unwrap();
+        assert_eq!(resp.status(), 404);
+
+        let uri = format!("/api/words/{}/tenses/{}", id, tense_id);
+        let resp = function_handler(&state, req("PATCH", &uri, r#"{ "sentence": "昨日寿司を食べた。" }"#)).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(word["word_tenses"][0]["sentence"], "昨日寿司を食べた。");
+
+        let resp = function_handler(&state, req("PATCH", &uri, r#"{ "tense_type": "Past_Negative" }"#)).await.unwrap();
+        let word: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(word["word_tenses"][0]["tense_type"], "past negative");
+        let resp = function_handler(&state, req("PATCH", &uri, r#"{ "tense_type": "provisional" }"#)).await.unwrap();
+        assert_eq!(resp.status(), 400);
+        let err: ErrorRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert!(err.error.message.starts_with("Invalid tense: Unknown tense_type: provisional, expected one of:"), "{}", err.error.message);
+        assert_eq!(function_handler(&state, req("PATCH", &format!("/api/words/{}/tenses/{}", id, tense_id + 100), "{}")).await.unwrap().status(), 404);
+
+        assert_eq!(function_handler(&state, req("DELETE", &uri, "")).await.unwrap().status(), 204);
+        assert_eq!(function_handler(&state, req("DELETE", &uri, "")).await.unwrap().status(), 404);
+        assert_eq!(state.db.get_word(id).await.unwrap().unwrap().word_tenses.len(), 1);
+
+        let uri = format!("/api/words/{}/sentences", id);
+        let resp = function_handler(&state, req("POST", &uri, r#"{ "sentence": " 寿司を食べる。", "sentence_translation": "I eat sushi.", "source": "よつばと！" }"#)).await.unwrap();
+        assert_eq!(resp.status(), 201);
+        let sentence: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(sentence["audio_status"], "pending");
+        assert_eq!(sentence["sentence"], "寿司を食べる。");
+        assert_eq!(function_handler(&state, req("POST", &uri, r#"{ "sentence": " " , "sentence_translation": "" }"#)).await.unwrap().status(), 400);
+        let resp = function_handler(&state, req("POST", &uri, r#"{ "sentence": "パンを食べる。", "sentence_translation": " " }"#)).await.unwrap();
+        assert_eq!(resp.status(), 400);
+        let err: ErrorRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(err.error.message, "Invalid sentence: sentence_translation must not be empty");
+        assert_eq!(function_handler(&state, req("POST", &format!("/api/words/{}/sentences", id + 1), r#"{ "sentence": "a", "sentence_translation": "b" }"#)).await.unwrap().status(), 404);
+        assert_eq!(state.db.get_word(id).await.unwrap().unwrap().sentences.len(), 1);
+
+        assert_eq!(function_handler(&state, req("DELETE", &format!("/api/words/{}", id), "")).await.unwrap().status(), 204);
+        assert!(state.db.get_word(id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejects_unauthenticated_requests_and_attributes_words() {
+        let mut state = state(Arc::new(MockLlmClient::new())).await;
+        state.auth = Auth::ApiKeys(vec![auth::ApiKey { name: "gpt".to_string(), key: "s3cret".to_string() }]);
+
+        let body = format!(r#"{{ "words": [{}] }}"#, fixture_word());
+        let post = |key: &str| lambda_http::http::Request::post("/api/words")
+            .header("authorization", format!("Bearer {}", key))
+            .body(Body::from(body.clone()))
+            .unwrap()
+            .with_query_string_parameters(HashMap::from([("req_type".to_string(), "chatgpt".to_string())]));
+
+        let resp = function_handler(&state, post("wrong")).await.unwrap();
+        assert_eq!(resp.status(), 401);
+        let get = lambda_http::http::Request::get("/api/words").body(Body::Empty).unwrap();
+        assert_eq!(function_handler(&state, get).await.unwrap().status(), 401);
+
+        let resp = function_handler(&state, post("s3cret")).await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let (words, _) = state.db.list_words(&Default::default()).await.unwrap();
+        assert_eq!(words[0].created_by.as_deref(), Some("gpt"));
+    }
+
+    #[tokio::test]
+    async fn serves_openapi_spec_without_a_key() {
+        let mut state = state(Arc::new(MockLlmClient::new())).await;
+        state.auth = Auth::ApiKeys(vec![auth::ApiKey { name: "gpt".to_string(), key: "s3cret".to_string() }]);
+
+        let get = lambda_http::http::Request::get("/default/openapi.json")
+            .header("host", "localhost:3000")
+            .body(Body::Empty)
+            .unwrap();
+        let resp = function_handler(&state, get).await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let spec: Value = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        assert_eq!(spec["openapi"], "3.1.0");
+        assert!(spec["paths"]["/api/words"]["post"].is_object());
+    }
+
+    #[tokio::test]
+    async fn on_conflict_param_picks_policy() {
+        let state = state(Arc::new(MockLlmClient::new())).await;
+        let word = fixture_word().to_string();
+        let post = |on_conflict: &str, body: String| {
+            let mut params = HashMap::from([("req_type".to_string(), "chatgpt".to_string())]);
+            if !on_conflict.is_empty() {
+                params.insert("on_conflict".to_string(), on_conflict.to_string());
+            }
+            lambda_http::http::Request::post("/api/words")
+                .body(Body::from(format!(r#"{{ "words": [{}] }}"#, body)))
+                .unwrap()
+                .with_query_string_parameters(params)
+        };
+        let status = |resp: Response<Body>| serde_json::from_slice::<NihongoWordSaveRes>(resp.body().as_ref()).unwrap().results[0].status;
+
+        assert_eq!(status(function_handler(&state, post("", word.clone())).await.unwrap()), NihongoWordSaveStatus::Created);
+
+        let merged = word.replace("毎朝", "今朝");
+        assert_eq!(status(function_handler(&state, post("", merged.clone())).await.unwrap()), NihongoWordSaveStatus::Duplicate);
+        assert_eq!(status(function_handler(&state, post("merge", merged.clone())).await.unwrap()), NihongoWordSaveStatus::Updated);
+        assert_eq!(status(function_handler(&state, post("merge", merged)).await.unwrap()), NihongoWordSaveStatus::Duplicate);
+
+        let resp = function_handler(&state, post("replace", word)).await.unwrap();
+        assert_eq!(resp.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn sentence_req_saves_each_extracted_word() {
+        let llm = Arc::new(MockLlmClient::from_fixture_dir(FIXTURE_DIR).unwrap());
+        let state = state(llm.clone()).await;
+        let word = serde_json::from_value::<NihongoWordOpenAiRes>(fixture_word()).unwrap();
+        add_to_table(state.db.as_ref(), word.into(), opts()).await;
+
+        // the recorded extraction lists パン twice, once with surrounding whitespace
+        let body = Body::from(r#"{ "sentence": "毎朝パンを食べる。", "source": { "title": "よつばと！" } }"#);
+        let resp = handle_sentence_req(&state, &body, opts()).await.unwrap();
+        assert_eq!(resp.status(), 200);
+
+        let res: NihongoWordSaveRes = serde_json::from_slice(resp.body().as_ref()).unwrap();
+        let statuses = res.results.iter().map(|r| (r.word.as_str(), r.status)).collect::<Vec<(&str, NihongoWordSaveStatus)>>();
+        assert_eq!(statuses, vec![
+            ("毎朝", NihongoWordSaveStatus::Created),
+            ("パン", NihongoWordSaveStatus::Created),
+            ("食べる", NihongoWordSaveStatus::Duplicate)
+        ]);
+
+        let calls = llm.calls();
+        assert_eq!(calls.len(), 4);
+        assert_eq!(calls[0][1], ChatMessage::user(get_sentence_user_prompt("毎朝パンを食べる。")));
+        assert_eq!(calls[2][1], ChatMessage::user(get_user_prompt("パン")));
+
+        let (words, total) = state.db.list_words(&Default::default()).await.unwrap();
+        assert_eq!(total, 3);
+        let saved = |w: &str| words.iter().find(|r| r.word == w).unwrap();
+        assert_eq!(saved("毎朝").word_reading, "まいあさ");
+        assert!(saved("毎朝").is_kanji);
+        assert_eq!(saved("パン").definition, "bread");
+        assert!(!saved("パン").is_kanji);
+        assert_eq!(saved("パン").source.as_ref().and_then(|s| s.title.as_deref()), Some("よつばと！"));
+        assert_eq!(saved("食べる").source, None);
+    }
+
+    #[tokio::test]
+    async fn routes_save_endpoints() {
+        let state = state(Arc::new(MockLlmClient::from_fixture_dir(FIXTURE_DIR).unwrap())).await;
+        let word = fixture_word().to_string();
+        let post = |uri: &str, params: &[(&str, &str)], body: String| lambda_http::http::Request::post(uri)
+            .body(Body::from(body))
+            .unwrap()
+            .with_query_string_parameters(params.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<String, String>>());
+        let status = |resp: Response<Body>| serde_json::from_slice::<NihongoWordSaveRes>(resp.body().as_ref()).unwrap().results[0].status;
+
+        let resp = function_handler(&state, post("/default/api/words/lookup", &[], r#"{ "word": "食べる" }"#.to_string())).await.unwrap();
+        assert_eq!(status(resp), NihongoWordSaveStatus::Created);
+
+        let batch = format!(r#"{{ "words": [{}] }}"#, word.replace("毎朝", "今朝"));
+        let resp = function_handler(&state, post("/api/words/batch", &[], batch.clone())).await.unwrap();
+        assert_eq!(status(resp), NihongoWordSaveStatus::Duplicate);
+        let resp = function_handler(&state, post("/api/words/batch", &[("on_conflict", "merge")], batch)).await.unwrap();
+        assert_eq!(status(resp), NihongoWordSaveStatus::Updated);
+
+        // the sentence prompt has no fixture, so the LLM fails
+        let resp = function_handler(&state, post("/api/sentences", &[], r#"{ "sentence": "猫が魚を食べた" }"#.to_string())).await.unwrap();
+        assert_eq!(resp.status(), 502);
+
+        // the req_type shim still works
+        let resp = function_handler(&state, post("/api/words", &[("req_type", "singular_word")], r#"{ "word": "食べる" }"#.to_string())).await.unwrap();
+        assert_eq!(status(resp), NihongoWordSaveStatus::Duplicate);
+        let resp = function_handler(&state, post("/api/words", &[], r#"{ "word": "食べる" }"#.to_string())).await.unwrap();
+        assert_eq!(resp.status(), 400);
+
+        let get = lambda_http::http::Request::get("/api/words/batch").body(Body::Empty).unwrap();
+        assert_eq!(function_handler(&state, get).await.unwrap().status(), 405);
+        let resp = function_handler(&state, post("/api/unknown", &[], "{}".to_string())).await.unwrap();
+        assert_eq!(resp.status(), 404);
+    }
+
+    #[test]
+    fn parses_req_type() {
+        assert!(matches!("singular_word".parse::<PostWordType>(), Ok(PostWordType::SingularWord)));
+        assert!(matches!("sentence".parse::<PostWordType>(), Ok(PostWordType::Sentence)));
+        assert!("words".parse::<PostWordType>().is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use lambda_http::{Body, Request, Response};
+use utoipa::{
+    Modify, OpenApi, PartialSchema, ToSchema,
+    openapi::{
+        self, RefOr, Ref, Schema,
+        schema::OneOfBuilder,
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        server::Server
+    }
+};
+use data::models::{db::ConflictPolicy, requests::{NihongoWordReqChatgpt, NihongoWordReq, NihongoSentenceReq}};
+
+use crate::http::json_response;
+
+/// Derived from the request / response types, served at `/openapi.json` for the GPT action to import
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Nihongo Word Persisting",
+        description = "Takes a word and saves it with mnemonics, definition, sentences, and tenses",
+        version = "v1.0.0"
+    ),
+    paths(
+        crate::handle_chatgpt_req,
+        crate::handle_singular_req,
+        crate::handle_sentence_req,
+        crate::post_handler,
+        crate::words::handle_list_words,
+        crate::words::handle_get_word,
+        crate::words::handle_update_word,
+        crate::words::handle_delete_word,
+        crate::words::handle_update_tense,
+        crate::words::handle_delete_tense,
+        crate::words::handle_add_sentence
+    ),
+    // only referenced from query params, which utoipa doesn't collect schemas from
+    components(schemas(crate::PostWordType, ConflictPolicy)),
+    modifiers(&SecuritySchemes),
+    security(("bearer" = []), ("api_key" = []))
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, doc: &mut openapi::OpenApi) {
+        let components = doc.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("bearer", SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()));
+        components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))));
+    }
+}
+
+/// Body of the `POST /api/words?req_type=` shim, one of the bodies of the routes `req_type` stands in for
+pub struct SaveWordsReq;
+
+impl PartialSchema for SaveWordsReq {
+    fn schema() -> RefOr<Schema> {
+        OneOfBuilder::new()
+            .item(Ref::from_schema_name(NihongoWordReqChatgpt::name()))
+            .item(Ref::from_schema_name(NihongoWordReq::name()))
+            .item(Ref::from_schema_name(NihongoSentenceReq::name()))
+            .into()
+    }
+}
+
+// the referenced schemas are collected from the routes that take them
+impl ToSchema for SaveWordsReq {}
+
+/// `GET /openapi.json`. The server is `API_PUBLIC_URL` when set, otherwise the host the spec was
+/// requested from plus `prefix` (the api gateway stage), so the GPT action calls the same deployment.
+pub fn handle_openapi(event: &Request, prefix: &[&str]) -> Result<Response<Body>, lambda_http::Error> {
+    let mut doc = ApiDoc::openapi();
+
+    let url = match std::env::var("API_PUBLIC_URL") {
+        Ok(url) => Some(url.trim_end_matches('/').to_string()),
+        Err(_) => server_url(event, prefix)
+    };
+    if let Some(url) = url {
+        doc.servers = Some(vec![Server::new(url)]);
+    }
+
+    json_response(200, &doc)
+}
+
+fn server_url(event: &Request, prefix: &[&str]) -> Option<String> {
+    let header = |name: &str| event.headers().get(name).and_then(|v| v.to_str().ok());
+
+    let host = header("host")?;
+    let proto = header("x-forwarded-proto").unwrap_or("http");
+    let prefix = prefix.iter().map(|s| format!("/{}", s)).collect::<String>();
+
+    Some(format!("{}://{}{}", proto, host, prefix))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_every_endpoint() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        let paths = doc["paths"].as_object().unwrap().keys().map(|k| k.as_str()).collect::<Vec<&str>>();
+        assert_eq!(paths, vec![
+            "/api/sentences",
+            "/api/words",
+            "/api/words/batch",
+            "/api/words/lookup",
+            "/api/words/{id}",
+            "/api/words/{id}/sentences",
+            "/api/words/{id}/tenses/{tense_id}"
+        ]);
+        assert_eq!(doc["paths"]["/api/words/batch"]["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"], "#/components/schemas/NihongoWordReqChatgpt");
+
+        let words = &doc["paths"]["/api/words"];
+        assert_eq!(words["post"]["operationId"], "ProcessNihongoSentenceWords");
+        let req_type = words["post"]["parameters"].as_array().unwrap().iter().find(|p| p["name"] == "req_type").unwrap();
+        assert_eq!(req_type["required"], true);
+
+        let schemas = &doc["components"]["schemas"];
+        assert_eq!(schemas["PostWordType"]["enum"], serde_json::json!(["chatgpt", "singular_word", "sentence"]));
+        let body = &words["post"]["requestBody"]["content"]["application/json"]["schema"];
+        assert_eq!(body["oneOf"], serde_json::json!([
+            { "$ref": "#/components/schemas/NihongoWordReqChatgpt" },
+            { "$ref": "#/components/schemas/NihongoWordReq" },
+            { "$ref": "#/components/schemas/NihongoSentenceReq" }
+        ]));
+        assert!(schemas.get("SaveWordsReq").is_none());
+        assert!(schemas["NihongoWordReqWordChatgpt"]["required"].as_array().unwrap().iter().all(|f| f != "kanji_mnemonic"));
+        assert!(doc["components"]["securitySchemes"]["bearer"].is_object());
+    }
+
+    #[test]
+    fn server_follows_request_host_and_stage() {
+        let event = lambda_http::http::Request::get("/default/openapi.json")
+            .header("host", "abc.execute-api.us-east-2.amazonaws.com")
+            .header("x-forwarded-proto", "https")
+            .body(Body::Empty)
+            .unwrap();
+
+        assert_eq!(server_url(&event, &["default"]).as_deref(), Some("https://abc.execute-api.us-east-2.amazonaws.com/default"));
+    }
+}
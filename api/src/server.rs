@@ -0,0 +1,119 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use hyper::{Server, service::{make_service_fn, service_fn}};
+use lambda_http::{Body, Request, RequestExt, aws_lambda_events::query_map::QueryMap};
+
+use data::models::responses::{ErrorCode, ErrorRes};
+
+use crate::{AppState, function_handler};
+
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0:3000";
+
+/// Serves the same handlers as the lambda from a long running hyper server,
+/// binds to `API_BIND_ADDR` (defaults to `0.0.0.0:3000`)
+pub async fn serve(state: Arc<AppState>) -> Result<()> {
+    let addr: SocketAddr = std::env::var("API_BIND_ADDR")
+        .unwrap_or_else(|_| DEFAULT_BIND_ADDR.to_string())
+        .parse()?;
+
+    let make_svc = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let state = state.clone();
+                async move { Ok::<_, Infallible>(handle(&state, req).await) }
+            }))
+        }
+    });
+
+    tracing::info!("Listening on http://{}", addr);
+
+    Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    Ok(())
+}
+
+async fn handle(state: &AppState, req: hyper::Request<hyper::Body>) -> hyper::Response<hyper::Body> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+
+    let res = match to_lambda_request(req).await {
+        Ok(event) => function_handler(state, event).await,
+        Err(e) => Err(e.into())
+    };
+
+    let resp = match res {
+        Ok(resp) => {
+            let (parts, body) = resp.into_parts();
+            let body = match body {
+                Body::Empty => hyper::Body::empty(),
+                Body::Text(t) => hyper::Body::from(t),
+                Body::Binary(b) => hyper::Body::from(b)
+            };
+            hyper::Response::from_parts(parts, body)
+        },
+        Err(e) => {
+            tracing::error!("Request failed | method: {} | path: {} | error: {}", method, path, e);
+            let body = serde_json::to_string(&ErrorRes::new(ErrorCode::Internal, "Internal server error")).unwrap_or_default();
+            let mut resp = hyper::Response::new(hyper::Body::from(body));
+            *resp.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+            resp.headers_mut().insert("content-type", hyper::header::HeaderValue::from_static("application/json"));
+            resp
+        }
+    };
+
+    tracing::info!("{} {} {}", method, path, resp.status().as_u16());
+    resp
+}
+
+/// Builds the request the way lambda_http would from an API gateway event, the handlers read
+/// query params from the request extensions rather than the uri
+async fn to_lambda_request(req: hyper::Request<hyper::Body>) -> Result<Request> {
+    let (parts, body) = req.into_parts();
+    let bytes = hyper::body::to_bytes(body).await?;
+
+    let query: QueryMap = parts.uri.query().unwrap_or_default().parse()?;
+    let body = if bytes.is_empty() { Body::Empty } else { Body::Binary(bytes.to_vec()) };
+
+    Ok(Request::from_parts(parts, body).with_query_string_parameters(query))
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        let mut term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {},
+            _ = term.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+
+    tracing::info!("Shutting down");
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn converts_query_and_body() {
+        let req = hyper::Request::post("/api/words?req_type=singular_word&req_type=ignored")
+            .body(hyper::Body::from(r#"{ "word": "食べる" }"#))
+            .unwrap();
+
+        let event = to_lambda_request(req).await.unwrap();
+        assert_eq!(event.query_string_parameters().first("req_type"), Some("singular_word"));
+        assert_eq!(event.uri().path(), "/api/words");
+        assert_eq!(event.body().as_ref(), r#"{ "word": "食べる" }"#.as_bytes());
+    }
+}
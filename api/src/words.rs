@@ -0,0 +1,351 @@
+use lambda_http::{Body, Response, aws_lambda_events::query_map::QueryMap};
+use chrono::{DateTime, NaiveDate, Utc, Duration};
+use data::{
+    models::{
+        db::{NihongoWordFilter, NihongoWordWithTensesStructured, NihongoWordSentence},
+        requests::{NihongoWordUpdateReq, NihongoWordTenseUpdateReq, NihongoWordSentenceReq},
+        responses::{NihongoWordListRes, ErrorCode, ErrorRes}
+    },
+    services::store::is_unique_violation,
+    validation::{validate_word_update, validate_tense_update, validate_sentence}
+};
+
+use crate::{AppState, http::{json_response, error_response, empty_response}};
+
+const MAX_LIMIT: i64 = 200;
+
+/// `GET /api/words`
+#[utoipa::path(
+    get,
+    path = "/api/words",
+    operation_id = "ListWords",
+    tag = "words",
+    description = "Saved words newest first with their tenses and example sentences",
+    params(
+        ("processed" = Option<bool>, Query, description = "Whether the worker already pushed the word to Anki"),
+        ("is_kanji" = Option<bool>, Query),
+        ("from" = Option<String>, Query, description = "RFC 3339 timestamp or YYYY-MM-DD"),
+        ("to" = Option<String>, Query, description = "RFC 3339 timestamp or YYYY-MM-DD, includes the whole day"),
+        ("q" = Option<String>, Query, description = "Substring of the word or its reading"),
+        ("limit" = Option<i64>, Query, description = "Defaults to 50, max 200"),
+        ("offset" = Option<i64>, Query)
+    ),
+    responses(
+        (status = 200, description = "Matching words and their total count", body = NihongoWordListRes),
+        (status = 400, description = "Invalid query param", body = ErrorRes)
+    )
+)]
+pub async fn handle_list_words(state: &AppState, params: &QueryMap) -> Result<Response<Body>, lambda_http::Error> {
+    let filter = match parse_word_filter(params) {
+        Ok(f) => f,
+        Err(e) => return error_response(ErrorCode::BadRequest, &e)
+    };
+
+    let (words, total) = state.db.list_words(&filter).await?;
+
+    json_response(200, &NihongoWordListRes {
+        words,
+        total,
+        limit: filter.limit,
+        offset: filter.offset
+    })
+}
+
+/// `GET /api/words/{id}`
+#[utoipa::path(
+    get,
+    path = "/api/words/{id}",
+    operation_id = "GetWord",
+    tag = "words",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, description = "The word with its tenses and sentences", body = NihongoWordWithTensesStructured),
+        (status = 404, description = "Word not found", body = ErrorRes)
+    )
+)]
+pub async fn handle_get_word(state: &AppState, id: &str) -> Result<Response<Body>, lambda_http::Error> {
+    let id: i64 = match id.parse() {
+        Ok(id) => id,
+        Err(_) => return error_response(ErrorCode::BadRequest, "Invalid word id")
+    };
+
+    match state.db.get_word(id).await? {
+        Some(w) => json_response(200, &w),
+        None => error_response(ErrorCode::NotFound, "Word not found")
+    }
+}
+
+/// `PUT|PATCH /api/words/{id}`, only the fields present in the body are changed.
+/// The word is marked unprocessed so the worker updates its anki note.
+#[utoipa::path(
+    method(patch, put),
+    path = "/api/words/{id}",
+    operation_id = "UpdateWord",
+    tag = "words",
+    description = "Changes only the fields in the body, the word's Anki note is updated on the worker's next run",
+    params(("id" = i64, Path)),
+    request_body = NihongoWordUpdateReq,
+    responses(
+        (status = 200, description = "The word with its tenses and sentences", body = NihongoWordWithTensesStructured),
+        (status = 400, description = "Invalid body", body = ErrorRes),
+        (status = 404, description = "Word not found", body = ErrorRes),
+        (status = 409, description = "Renamed to a word that's already saved", body = ErrorRes)
+    )
+)]
+pub async fn handle_update_word(state: &AppState, id: &str, body: &Body) -> Result<Response<Body>, lambda_http::Error> {
+    let id: i64 = match id.parse() {
+        Ok(id) => id,
+        Err(_) => return error_response(ErrorCode::BadRequest, "Invalid word id")
+    };
+
+    let mut update = match serde_json::from_slice::<NihongoWordUpdateReq>(body.as_ref()) {
+        Ok(u) => u.into(),
+        Err(e) => return error_response(ErrorCode::BadRequest, &format!("Invalid body: {}", e))
+    };
+
+    let saved = match state.db.get_word(id).await? {
+        Some(w) => w,
+        None => return error_response(ErrorCode::NotFound, "Word not found")
+    };
+    if let Err(e) = validate_word_update(&saved, &mut update) {
+        return error_response(ErrorCode::BadRequest, &format!("Invalid word: {}", e));
+    }
+
+    match state.db.update_word(id, &update).await {
+        Ok(true) => {},
+        Ok(false) => return error_response(ErrorCode::NotFound, "Word not found"),
+        Err(e) if is_unique_violation(&e) => return error_response(ErrorCode::Conflict, "Word already exists"),
+        Err(e) => return Err(e.into())
+    }
+
+    match state.db.get_word(id).await? {
+        Some(w) => json_response(200, &w),
+        None => error_response(ErrorCode::NotFound, "Word not found")
+    }
+}
+
+/// `DELETE /api/words/{id}`, deletes the word and its tenses
+#[utoipa::path(
+    delete,
+    path = "/api/words/{id}",
+    operation_id = "DeleteWord",
+    tag = "words",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "Word not found", body = ErrorRes)
+    )
+)]
+pub async fn handle_delete_word(state: &AppState, id: &str) -> Result<Response<Body>, lambda_http::Error> {
+    let id: i64 = match id.parse() {
+        Ok(id) => id,
+        Err(_) => return error_response(ErrorCode::BadRequest, "Invalid word id")
+    };
+
+    match state.db.delete_word(id).await? {
+        true => empty_response(204),
+        false => error_response(ErrorCode::NotFound, "Word not found")
+    }
+}
+
+/// `PUT|PATCH /api/words/{id}/tenses/{tense_id}`, marks the word unprocessed like a word update
+#[utoipa::path(
+    method(patch, put),
+    path = "/api/words/{id}/tenses/{tense_id}",
+    operation_id = "UpdateTense",
+    tag = "words",
+    params(("id" = i64, Path), ("tense_id" = i64, Path)),
+    request_body = NihongoWordTenseUpdateReq,
+    responses(
+        (status = 200, description = "The word the tense belongs to", body = NihongoWordWithTensesStructured),
+        (status = 400, description = "Invalid body", body = ErrorRes),
+        (status = 404, description = "Tense not found", body = ErrorRes)
+    )
+)]
+pub async fn handle_update_tense(state: &AppState, id: &str, tense_id: &str, body: &Body) -> Result<Response<Body>, lambda_http::Error> {
+    let (id, tense_id): (i64, i64) = match (id.parse(), tense_id.parse()) {
+        (Ok(id), Ok(tense_id)) => (id, tense_id),
+        _ => return error_response(ErrorCode::BadRequest, "Invalid word or tense id")
+    };
+
+    let mut update = match serde_json::from_slice::<NihongoWordTenseUpdateReq>(body.as_ref()) {
+        Ok(u) => u.into(),
+        Err(e) => return error_response(ErrorCode::BadRequest, &format!("Invalid body: {}", e))
+    };
+
+    let saved = state.db.get_word(id).await?
+        .and_then(|w| w.word_tenses.into_iter().find(|t| t.id == tense_id));
+    let Some(saved) = saved else {
+        return error_response(ErrorCode::NotFound, "Tense not found");
+    };
+    if let Err(e) = validate_tense_update(&saved, &mut update) {
+        return error_response(ErrorCode::BadRequest, &format!("Invalid tense: {}", e));
+    }
+
+    if !state.db.update_word_tense(id, tense_id, &update).await? {
+        return error_response(ErrorCode::NotFound, "Tense not found");
+    }
+
+    match state.db.get_word(id).await? {
+        Some(w) => json_response(200, &w),
+        None => error_response(ErrorCode::NotFound, "Word not found")
+    }
+}
+
+/// `DELETE /api/words/{id}/tenses/{tense_id}`
+#[utoipa::path(
+    delete,
+    path = "/api/words/{id}/tenses/{tense_id}",
+    operation_id = "DeleteTense",
+    tag = "words",
+    params(("id" = i64, Path), ("tense_id" = i64, Path)),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "Tense not found", body = ErrorRes)
+    )
+)]
+pub async fn handle_delete_tense(state: &AppState, id: &str, tense_id: &str) -> Result<Response<Body>, lambda_http::Error> {
+    let (id, tense_id): (i64, i64) = match (id.parse(), tense_id.parse()) {
+        (Ok(id), Ok(tense_id)) => (id, tense_id),
+        _ => return error_response(ErrorCode::BadRequest, "Invalid word or tense id")
+    };
+
+    match state.db.delete_word_tense(id, tense_id).await? {
+        true => empty_response(204),
+        false => error_response(ErrorCode::NotFound, "Tense not found")
+    }
+}
+
+/// `POST /api/words/{id}/sentences`, adds another example sentence and marks the word unprocessed
+#[utoipa::path(
+    post,
+    path = "/api/words/{id}/sentences",
+    operation_id = "AddSentence",
+    tag = "words",
+    params(("id" = i64, Path)),
+    request_body = NihongoWordSentenceReq,
+    responses(
+        (status = 201, description = "The added sentence", body = NihongoWordSentence),
+        (status = 400, description = "Invalid body", body = ErrorRes),
+        (status = 404, description = "Word not found", body = ErrorRes)
+    )
+)]
+pub async fn handle_add_sentence(state: &AppState, id: &str, body: &Body) -> Result<Response<Body>, lambda_http::Error> {
+    let id: i64 = match id.parse() {
+        Ok(id) => id,
+        Err(_) => return error_response(ErrorCode::BadRequest, "Invalid word id")
+    };
+
+    let mut sentence = match serde_json::from_slice::<NihongoWordSentenceReq>(body.as_ref()) {
+        Ok(s) => s.into(),
+        Err(e) => return error_response(ErrorCode::BadRequest, &format!("Invalid body: {}", e))
+    };
+
+    if let Err(e) = validate_sentence(&mut sentence) {
+        return error_response(ErrorCode::BadRequest, &format!("Invalid sentence: {}", e));
+    }
+
+    match state.db.add_word_sentence(id, &sentence).await? {
+        Some(s) => json_response(201, &s),
+        None => error_response(ErrorCode::NotFound, "Word not found")
+    }
+}
+
+/// Query params: `processed`, `is_kanji`, `from`, `to` (RFC 3339 or `YYYY-MM-DD`, `to` is inclusive for dates),
+/// `q` (substring of the word or reading), `limit` (max 200) and `offset`
+fn parse_word_filter(params: &QueryMap) -> Result<NihongoWordFilter, String> {
+    let mut filter = NihongoWordFilter::default();
+
+    if let Some(v) = params.first("processed") {
+        filter.is_processed = Some(v.parse().map_err(|_| "processed must be true or false")?);
+    }
+    if let Some(v) = params.first("is_kanji") {
+        filter.is_kanji = Some(v.parse().map_err(|_| "is_kanji must be true or false")?);
+    }
+    if let Some(v) = params.first("from") {
+        filter.created_from = Some(parse_date(v, false).ok_or("from must be an RFC 3339 timestamp or YYYY-MM-DD")?);
+    }
+    if let Some(v) = params.first("to") {
+        filter.created_to = Some(parse_date(v, true).ok_or("to must be an RFC 3339 timestamp or YYYY-MM-DD")?);
+    }
+    if let Some(v) = params.first("q") {
+        if !v.trim().is_empty() {
+            filter.search = Some(v.trim().to_string());
+        }
+    }
+    if let Some(v) = params.first("limit") {
+        filter.limit = v.parse().ok()
+            .filter(|l| (1..=MAX_LIMIT).contains(l))
+            .ok_or(format!("limit must be between 1 and {}", MAX_LIMIT))?;
+    }
+    if let Some(v) = params.first("offset") {
+        filter.offset = v.parse().ok()
+            .filter(|o: &i64| *o >= 0)
+            .ok_or("offset must be a positive number")?;
+    }
+
+    Ok(filter)
+}
+
+/// Dates without a time are the start of that day, or the start of the next day when used as an exclusive upper bound
+fn parse_date(v: &str, end_of_day: bool) -> Option<DateTime<Utc>> {
+    if let Ok(d) = DateTime::parse_from_rfc3339(v) {
+        return Some(d.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(v, "%Y-%m-%d").ok()?;
+    let date = if end_of_day { date + Duration::days(1) } else { date };
+
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn params(p: &[(&str, &str)]) -> QueryMap {
+        p.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<HashMap<String, String>>().into()
+    }
+
+    #[test]
+    fn parses_filter() {
+        let filter = parse_word_filter(&params(&[
+            ("processed", "false"),
+            ("is_kanji", "true"),
+            ("from", "2024-01-01"),
+            ("to", "2024-01-31"),
+            ("q", " 食べ "),
+            ("limit", "10"),
+            ("offset", "20")
+        ])).unwrap();
+
+        assert_eq!(filter.is_processed, Some(false));
+        assert_eq!(filter.is_kanji, Some(true));
+        assert_eq!(filter.created_from.unwrap().to_rfc3339(), "2024-01-01T00:00:00+00:00");
+        assert_eq!(filter.created_to.unwrap().to_rfc3339(), "2024-02-01T00:00:00+00:00");
+        assert_eq!(filter.search.as_deref(), Some("食べ"));
+        assert_eq!((filter.limit, filter.offset), (10, 20));
+    }
+
+    #[test]
+    fn defaults_without_params() {
+        assert_eq!(parse_word_filter(&params(&[])).unwrap(), NihongoWordFilter::default());
+    }
+
+    #[test]
+    fn rejects_invalid_params() {
+        assert!(parse_word_filter(&params(&[("processed", "yes")])).is_err());
+        assert!(parse_word_filter(&params(&[("limit", "0")])).is_err());
+        assert!(parse_word_filter(&params(&[("limit", "1000")])).is_err());
+        assert!(parse_word_filter(&params(&[("offset", "-1")])).is_err());
+        assert!(parse_word_filter(&params(&[("from", "yesterday")])).is_err());
+    }
+
+    #[test]
+    fn parses_rfc3339_dates() {
+        let d = parse_date("2024-01-26T22:16:00+09:00", true).unwrap();
+        assert_eq!(d.to_rfc3339(), "2024-01-26T13:16:00+00:00");
+    }
+}
@@ -0,0 +1,28 @@
+-- `if not exists` so databases created from the old README script can adopt the migrations as is
+create table if not exists
+  public.nihongo_word (
+    id bigint generated by default as identity,
+    word text not null,
+    definition text not null,
+    sentence text not null,
+    is_processed boolean not null default false,
+    kanji_mnemonic text null default ''::text,
+    spoken_mnemonic text null,
+    word_reading text not null,
+    sentence_translation text not null,
+    created_at timestamp with time zone not null default now(),
+    constraint nihongo_word_pkey primary key (id),
+    constraint uq_word unique (word)
+  );
+
+create table if not exists
+  public.nihongo_word_tense (
+    id bigint generated by default as identity,
+    word_id bigint not null,
+    word text not null,
+    sentence text not null,
+    tense_type text not null,
+    created_at timestamp with time zone not null default now(),
+    constraint nihongo_word_tense_pkey primary key (id),
+    constraint nihongo_word_tense_word_id_fkey foreign key (word_id) references nihongo_word (id) on update cascade on delete cascade
+  );
@@ -0,0 +1,2 @@
+alter table public.nihongo_word
+  add column if not exists is_kanji boolean not null default false;
@@ -0,0 +1,16 @@
+-- example sentences besides the one on nihongo_word, e.g. the same word found in another book
+create table if not exists
+  public.nihongo_word_sentence (
+    id bigint generated by default as identity,
+    word_id bigint not null,
+    sentence text not null,
+    sentence_translation text not null,
+    source text null,
+    audio_status text not null default 'pending',
+    created_at timestamp with time zone not null default now(),
+    constraint nihongo_word_sentence_pkey primary key (id),
+    constraint nihongo_word_sentence_word_id_fkey foreign key (word_id) references nihongo_word (id) on update cascade on delete cascade,
+    constraint nihongo_word_sentence_audio_status_check check (audio_status in ('pending', 'generated', 'failed'))
+  );
+
+create index if not exists idx_nihongo_word_sentence_word_id on public.nihongo_word_sentence (word_id);
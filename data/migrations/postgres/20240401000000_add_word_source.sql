@@ -0,0 +1,6 @@
+-- where the word was found: book / site title, url, chapter or page and when it was read
+alter table public.nihongo_word
+  add column if not exists source_title text null,
+  add column if not exists source_url text null,
+  add column if not exists source_location text null,
+  add column if not exists seen_at timestamp with time zone null;
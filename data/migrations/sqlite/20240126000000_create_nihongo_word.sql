@@ -0,0 +1,26 @@
+create table if not exists nihongo_word (
+    id integer primary key autoincrement,
+    word text not null,
+    definition text not null,
+    sentence text not null,
+    is_processed boolean not null default false,
+    kanji_mnemonic text null default '',
+    spoken_mnemonic text null,
+    word_reading text not null,
+    sentence_translation text not null,
+    is_kanji boolean not null default false,
+    created_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    constraint uq_word unique (word)
+);
+
+create table if not exists nihongo_word_tense (
+    id integer primary key autoincrement,
+    word_id integer not null,
+    word text not null,
+    sentence text not null,
+    tense_type text not null,
+    created_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    constraint nihongo_word_tense_word_id_fkey foreign key (word_id) references nihongo_word (id) on update cascade on delete cascade
+);
+
+create index if not exists idx_nihongo_word_tense_word_id on nihongo_word_tense (word_id);
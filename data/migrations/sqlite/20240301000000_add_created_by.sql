@@ -0,0 +1,3 @@
+-- name of the api key the word was saved with, null for words saved before auth or with auth disabled
+alter table nihongo_word
+  add column created_by text null;
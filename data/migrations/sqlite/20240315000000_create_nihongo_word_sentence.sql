@@ -0,0 +1,13 @@
+-- example sentences besides the one on nihongo_word, e.g. the same word found in another book
+create table if not exists nihongo_word_sentence (
+    id integer primary key autoincrement,
+    word_id integer not null,
+    sentence text not null,
+    sentence_translation text not null,
+    source text null,
+    audio_status text not null default 'pending' check (audio_status in ('pending', 'generated', 'failed')),
+    created_at text not null default (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    constraint nihongo_word_sentence_word_id_fkey foreign key (word_id) references nihongo_word (id) on update cascade on delete cascade
+);
+
+create index if not exists idx_nihongo_word_sentence_word_id on nihongo_word_sentence (word_id);
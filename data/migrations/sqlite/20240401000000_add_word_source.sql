@@ -0,0 +1,5 @@
+-- where the word was found: book / site title, url, chapter or page and when it was read
+alter table nihongo_word add column source_title text null;
+alter table nihongo_word add column source_url text null;
+alter table nihongo_word add column source_location text null;
+alter table nihongo_word add column seen_at text null;
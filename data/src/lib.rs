@@ -1,2 +1,3 @@
 pub mod services;
 pub mod models;
+pub mod validation;
@@ -1,20 +1,31 @@
+use std::{fmt, str::FromStr};
 use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
 use sqlx::types::chrono::{DateTime, Utc};
 
-#[derive(sqlx::FromRow, Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct NihongoWordWithTensesStructured {
     pub id: i64,
     pub word: String,
+    pub is_kanji: bool,
+    pub word_reading: String,
     pub definition: String,
     pub sentence: String,
+    pub sentence_translation: String,
     pub kanji_mnemonic: Option<String>,
     pub spoken_mnemonic: Option<String>,
     pub is_processed: bool,
     pub word_tenses: Vec<NihongoWordWithTensesStructuredTenses>,
+    /// Example sentences besides `sentence`, oldest first
+    pub sentences: Vec<NihongoWordSentence>,
+    /// Name of the api key the word was saved with
+    pub created_by: Option<String>,
+    /// Where the word was found, `None` when no part of it was given
+    pub source: Option<NihongoWordSource>,
     pub created_at: DateTime<Utc>
 }
 
-#[derive(sqlx::FromRow, Debug, Clone, PartialEq)]
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct NihongoWordWithTensesStructuredTenses {
     pub id: i64,
     pub word_id: i64,
@@ -24,6 +35,161 @@ pub struct NihongoWordWithTensesStructuredTenses {
     pub created_at: DateTime<Utc>
 }
 
+/// A `nihongo_word` row without its tenses
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq)]
+pub struct NihongoWordRow {
+    pub id: i64,
+    pub word: String,
+    pub is_kanji: bool,
+    pub word_reading: String,
+    pub definition: String,
+    pub sentence: String,
+    pub sentence_translation: String,
+    pub kanji_mnemonic: Option<String>,
+    pub spoken_mnemonic: Option<String>,
+    pub is_processed: bool,
+    pub created_by: Option<String>,
+    pub source_title: Option<String>,
+    pub source_url: Option<String>,
+    pub source_location: Option<String>,
+    pub seen_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>
+}
+
+impl NihongoWordRow {
+    pub fn with_tenses(self, word_tenses: Vec<NihongoWordWithTensesStructuredTenses>) -> NihongoWordWithTensesStructured {
+        NihongoWordWithTensesStructured {
+            id: self.id,
+            word: self.word,
+            is_kanji: self.is_kanji,
+            word_reading: self.word_reading,
+            definition: self.definition,
+            sentence: self.sentence,
+            sentence_translation: self.sentence_translation,
+            kanji_mnemonic: self.kanji_mnemonic,
+            spoken_mnemonic: self.spoken_mnemonic,
+            is_processed: self.is_processed,
+            word_tenses,
+            sentences: vec![],
+            created_by: self.created_by,
+            source: NihongoWordSource {
+                title: self.source_title,
+                url: self.source_url,
+                location: self.source_location,
+                seen_at: self.seen_at
+            }.non_empty(),
+            created_at: self.created_at
+        }
+    }
+}
+
+/// Where a word was found, every part is optional
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NihongoWordSource {
+    /// Book, manga, site or show, e.g. `NHK Easy`
+    pub title: Option<String>,
+    pub url: Option<String>,
+    /// Chapter, page or episode, e.g. `Vol. 3 p. 42`
+    pub location: Option<String>,
+    /// When the word was read
+    pub seen_at: Option<DateTime<Utc>>
+}
+
+impl NihongoWordSource {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.url.is_none() && self.location.is_none() && self.seen_at.is_none()
+    }
+
+    pub fn non_empty(self) -> Option<Self> {
+        if self.is_empty() { None } else { Some(self) }
+    }
+
+    /// `title, location` for the `source` of a merged example sentence
+    pub fn label(&self) -> Option<String> {
+        match (&self.title, &self.location) {
+            (Some(t), Some(l)) => Some(format!("{}, {}", t, l)),
+            (Some(t), None) => Some(t.clone()),
+            (None, Some(l)) => Some(l.clone()),
+            (None, None) => None
+        }
+    }
+}
+
+/// Attaches each tense to its word, tenses are expected in the order they should be listed
+pub fn group_tenses(words: Vec<NihongoWordRow>, tenses: Vec<NihongoWordWithTensesStructuredTenses>) -> Vec<NihongoWordWithTensesStructured> {
+    let mut res = words.into_iter()
+        .map(|w| w.with_tenses(vec![]))
+        .collect::<Vec<NihongoWordWithTensesStructured>>();
+
+    for t in tenses {
+        if let Some(w) = res.iter_mut().find(|w| w.id == t.word_id) {
+            w.word_tenses.push(t);
+        }
+    }
+
+    res
+}
+
+/// Attaches each sentence to its word, sentences are expected in the order they should be listed
+pub fn group_sentences(words: &mut [NihongoWordWithTensesStructured], sentences: Vec<NihongoWordSentence>) {
+    for s in sentences {
+        if let Some(w) = words.iter_mut().find(|w| w.id == s.word_id) {
+            w.sentences.push(s);
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NihongoWordSentence {
+    pub id: i64,
+    pub word_id: i64,
+    pub sentence: String,
+    pub sentence_translation: String,
+    /// Where the sentence was found, e.g. a book title
+    pub source: Option<String>,
+    /// `pending`, `generated` or `failed`, whether the worker generated the sentence's audio
+    pub audio_status: String,
+    pub created_at: DateTime<Utc>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NihongoWordSentenceInsert {
+    pub sentence: String,
+    pub sentence_translation: String,
+    pub source: Option<String>
+}
+
+pub const AUDIO_STATUS_PENDING: &str = "pending";
+pub const AUDIO_STATUS_GENERATED: &str = "generated";
+pub const AUDIO_STATUS_FAILED: &str = "failed";
+
+/// Filters for listing saved words, `None` means don't filter on that column
+#[derive(Debug, Clone, PartialEq)]
+pub struct NihongoWordFilter {
+    pub is_processed: Option<bool>,
+    pub is_kanji: Option<bool>,
+    pub created_from: Option<DateTime<Utc>>,
+    pub created_to: Option<DateTime<Utc>>,
+    /// Substring of the word or its reading
+    pub search: Option<String>,
+    pub limit: i64,
+    pub offset: i64
+}
+
+impl Default for NihongoWordFilter {
+    fn default() -> Self {
+        NihongoWordFilter {
+            is_processed: None,
+            is_kanji: None,
+            created_from: None,
+            created_to: None,
+            search: None,
+            limit: 50,
+            offset: 0
+        }
+    }
+}
+
 #[derive(sqlx::FromRow, Debug, Clone, PartialEq)]
 pub struct NihongoWordWithTenses {
     pub id: i64,
@@ -38,7 +204,9 @@ pub struct NihongoWordWithTenses {
     pub word_id: Option<i64>,
     pub tense_word: Option<String>,
     pub tense_sentence: Option<String>,
-    pub tense_type: Option<String>
+    pub tense_type: Option<String>,
+    pub source_title: Option<String>,
+    pub source_url: Option<String>
 }
 
 
@@ -52,7 +220,10 @@ pub struct NihongoWordInsert {
     pub sentence_translation: String,
     pub kanji_mnemonic: Option<String>,
     pub spoken_mnemonic: Option<String>,
-    pub word_tenses: Vec<NihongoWordTenseInsert>
+    pub word_tenses: Vec<NihongoWordTenseInsert>,
+    /// Name of the api key the word is saved with, set by the api rather than the request body
+    pub created_by: Option<String>,
+    pub source: NihongoWordSource
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -61,3 +232,78 @@ pub struct NihongoWordTenseInsert {
     pub sentence: String,
     pub tense_type: String
 }
+
+/// Partial update of a word, `None` leaves the column as is and `Some(None)` clears a nullable one
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NihongoWordUpdate {
+    pub word: Option<String>,
+    pub is_kanji: Option<bool>,
+    pub word_reading: Option<String>,
+    pub definition: Option<String>,
+    pub sentence: Option<String>,
+    pub sentence_translation: Option<String>,
+    pub kanji_mnemonic: Option<Option<String>>,
+    pub spoken_mnemonic: Option<Option<String>>
+}
+
+/// Partial update of a tense, `None` leaves the column as is
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NihongoWordTenseUpdate {
+    pub word: Option<String>,
+    pub sentence: Option<String>,
+    pub tense_type: Option<String>
+}
+
+/// What saving a word that already exists (`uq_word`) does
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[schema(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the saved word as is
+    #[default]
+    Skip,
+    /// Replace the saved word and its tenses with the new ones
+    Overwrite,
+    /// Add the new example sentence and any tense types the saved word doesn't have yet
+    Merge
+}
+
+#[derive(Debug)]
+pub struct ParseConflictPolicyError;
+
+impl fmt::Display for ParseConflictPolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown conflict policy, expected one of: skip, overwrite, merge")
+    }
+}
+
+impl std::error::Error for ParseConflictPolicyError {}
+
+impl FromStr for ConflictPolicy {
+    type Err = ParseConflictPolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "merge" => Ok(ConflictPolicy::Merge),
+            _ => Err(ParseConflictPolicyError)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NihongoWordInsertOutcome {
+    Created(i64),
+    /// The word already existed and wasn't changed
+    Duplicate(i64),
+    /// The word already existed and was overwritten or merged into, it's unprocessed again
+    Updated(i64)
+}
+
+impl NihongoWordInsertOutcome {
+    pub fn id(&self) -> i64 {
+        match self {
+            NihongoWordInsertOutcome::Created(id) | NihongoWordInsertOutcome::Duplicate(id) | NihongoWordInsertOutcome::Updated(id) => *id
+        }
+    }
+}
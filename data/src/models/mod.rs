@@ -1,3 +1,4 @@
 pub mod db;
 pub mod requests;
+pub mod responses;
 pub mod oai;
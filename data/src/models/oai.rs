@@ -1,73 +1,180 @@
-use std::convert::Into;
+use std::{fmt, str::FromStr};
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 
-use super::db::{NihongoWordInsert, NihongoWordTenseInsert};
+use super::db::{NihongoWordInsert, NihongoWordTenseInsert, NihongoWordSource};
 
 #[derive(Clone, Debug)]
 pub struct Prompt {
     pub system_prompt: String,
-    pub user_prompt: String,
-    pub model: String,
-    pub provider: ModelProvider 
+    pub user_prompt: String
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ModelProvider {
     OpenAi,
-    Mistral
+    Mistral,
+    Anthropic,
+    /// Ollama, llama.cpp server or anything else exposing an OpenAI compatible endpoint
+    Local
+}
+
+#[derive(Debug)]
+pub struct ParseModelProviderError;
+
+impl fmt::Display for ParseModelProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown model provider, expected one of: openai, mistral, anthropic, local")
+    }
+}
+
+impl std::error::Error for ParseModelProviderError {}
+
+impl FromStr for ModelProvider {
+    type Err = ParseModelProviderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "openai" => Ok(ModelProvider::OpenAi),
+            "mistral" => Ok(ModelProvider::Mistral),
+            "anthropic" => Ok(ModelProvider::Anthropic),
+            "local" | "ollama" | "llamacpp" => Ok(ModelProvider::Local),
+            _ => Err(ParseModelProviderError)
+        }
+    }
 }
 
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The LLM's output is checked against the `JsonSchema` of these types before deserializing,
+/// so the `length` bounds are what gets an empty string sent back to the model
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct NihongoWordOpenAiRes {
+    #[schemars(length(min = 1))]
     pub word: String,
     pub is_kanji: bool,
+    #[schemars(length(min = 1))]
     pub word_reading: String,
+    #[schemars(length(min = 1))]
     pub definition: String,
+    #[schemars(length(min = 1))]
     pub sentence: String,
+    #[schemars(length(min = 1))]
     pub sentence_translation: String,
     pub kanji_mnemonic: Option<String>,
     pub spoken_mnemonic: Option<String>,
     pub word_tenses: Vec<NihongoWordOpenAiResTense>
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct NihongoWordOpenAiResTense {
+    #[schemars(length(min = 1))]
     pub word: String,
+    #[schemars(length(min = 1))]
     pub sentence: String,
+    #[schemars(length(min = 1))]
     pub tense_type: String
 }
 
+/// Vocab words (base form, particles excluded) extracted from a sentence
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct NihongoSentenceWordsOpenAiRes {
+    pub words: Vec<String>
+}
+
 
-impl Into<NihongoWordInsert> for NihongoWordOpenAiRes {
-    fn into(self) -> NihongoWordInsert {
+impl From<NihongoWordOpenAiRes> for NihongoWordInsert {
+    fn from(w: NihongoWordOpenAiRes) -> Self {
         let mut tenses = vec![];
 
-        for t in self.word_tenses {
+        for t in w.word_tenses {
             let new_tense: NihongoWordTenseInsert = t.into();
             tenses.push(new_tense);
         }
 
         NihongoWordInsert {
-            word: self.word,
-            is_kanji: self.is_kanji,
-            word_reading: self.word_reading,
-            definition: self.definition,
-            sentence: self.sentence,
-            sentence_translation: self.sentence_translation,
-            kanji_mnemonic: self.kanji_mnemonic,
-            spoken_mnemonic: self.spoken_mnemonic,
-            word_tenses: tenses
+            word: w.word,
+            is_kanji: w.is_kanji,
+            word_reading: w.word_reading,
+            definition: w.definition,
+            sentence: w.sentence,
+            sentence_translation: w.sentence_translation,
+            kanji_mnemonic: w.kanji_mnemonic,
+            spoken_mnemonic: w.spoken_mnemonic,
+            word_tenses: tenses,
+            created_by: None,
+            source: NihongoWordSource::default()
         }
     }
 }
 
-impl Into<NihongoWordTenseInsert> for NihongoWordOpenAiResTense {
-    fn into(self) -> NihongoWordTenseInsert {
+impl From<NihongoWordOpenAiResTense> for NihongoWordTenseInsert {
+    fn from(t: NihongoWordOpenAiResTense) -> Self {
         NihongoWordTenseInsert {
-            word: self.word,
-            sentence: self.sentence,
-            tense_type: self.tense_type
+            word: t.word,
+            sentence: t.sentence,
+            tense_type: t.tense_type
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_word_with_tenses() {
+        let json = r#"{
+            "word": "食べる",
+            "is_kanji": true,
+            "word_reading": "たべる",
+            "definition": "to eat",
+            "sentence": "毎朝パンを食べる。",
+            "sentence_translation": "I eat bread every morning.",
+            "kanji_mnemonic": "A person under a roof eating",
+            "spoken_mnemonic": "Tabby cat eats",
+            "word_tenses": [
+                { "word": "食べた", "sentence": "昨日パンを食べた。", "tense_type": "past" }
+            ]
+        }"#;
+
+        let res: NihongoWordOpenAiRes = serde_json::from_str(json).unwrap();
+        assert_eq!(res.word_tenses.len(), 1);
+        assert_eq!(res.word_tenses[0].tense_type, "past");
+
+        let insert: NihongoWordInsert = res.into();
+        assert_eq!(insert.word, "食べる");
+        assert_eq!(insert.word_tenses[0].word, "食べた");
+    }
+
+    #[test]
+    fn mnemonics_are_optional() {
+        let json = r#"{
+            "word": "すごい",
+            "is_kanji": false,
+            "word_reading": "すごい",
+            "definition": "amazing",
+            "sentence": "すごいですね。",
+            "sentence_translation": "That's amazing.",
+            "kanji_mnemonic": null,
+            "word_tenses": []
+        }"#;
+
+        let res: NihongoWordOpenAiRes = serde_json::from_str(json).unwrap();
+        assert_eq!(res.kanji_mnemonic, None);
+        assert_eq!(res.spoken_mnemonic, None);
+    }
+
+    #[test]
+    fn rejects_missing_required_fields() {
+        let json = r#"{ "word": "食べる", "is_kanji": true, "word_tenses": [] }"#;
+        assert!(serde_json::from_str::<NihongoWordOpenAiRes>(json).is_err());
+    }
+
+    #[test]
+    fn parses_model_provider() {
+        assert_eq!("OpenAI".parse::<ModelProvider>().unwrap(), ModelProvider::OpenAi);
+        assert_eq!("ollama".parse::<ModelProvider>().unwrap(), ModelProvider::Local);
+        assert!("gemini".parse::<ModelProvider>().is_err());
+    }
+}
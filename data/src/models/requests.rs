@@ -1,18 +1,29 @@
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Deserializer};
+use utoipa::ToSchema;
 
-use super::db::{NihongoWordInsert, NihongoWordTenseInsert};
+use super::db::{NihongoWordInsert, NihongoWordTenseInsert, NihongoWordUpdate, NihongoWordTenseUpdate, NihongoWordSentenceInsert, NihongoWordSource};
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct NihongoWordReq {
     pub word: String,
+    pub source: Option<NihongoWordSource>
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NihongoSentenceReq {
+    pub sentence: String,
+    /// Saved on every word extracted from the sentence
+    pub source: Option<NihongoWordSource>
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct NihongoWordReqChatgpt {
     pub words: Vec<NihongoWordReqWordChatgpt>,
+    /// Saved on every word that doesn't have its own `source`
+    pub source: Option<NihongoWordSource>
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct NihongoWordReqWordChatgpt {
     pub word: String,
     pub is_kanji: bool,
@@ -22,45 +33,127 @@ pub struct NihongoWordReqWordChatgpt {
     pub sentence_translation: String,
     pub kanji_mnemonic: Option<String>,
     pub spoken_mnemonic: Option<String>,
-    pub word_tenses: Vec<NihongoWordReqTenseChatgpt>
+    pub word_tenses: Vec<NihongoWordReqTenseChatgpt>,
+    pub source: Option<NihongoWordSource>
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct NihongoWordReqTenseChatgpt {
     pub word: String,
     pub sentence: String,
     pub tense_type: String
 }
 
-impl Into<NihongoWordInsert> for NihongoWordReqWordChatgpt {
-    fn into(self) -> NihongoWordInsert {
+impl From<NihongoWordReqWordChatgpt> for NihongoWordInsert {
+    fn from(w: NihongoWordReqWordChatgpt) -> Self {
         let mut tenses = vec![];
 
-        for t in self.word_tenses {
+        for t in w.word_tenses {
             let new_tense: NihongoWordTenseInsert = t.into();
             tenses.push(new_tense);
         }
 
         NihongoWordInsert {
-            word: self.word,
-            is_kanji: self.is_kanji,
-            word_reading: self.word_reading,
-            definition: self.definition,
-            sentence: self.sentence,
-            sentence_translation: self.sentence_translation,
-            kanji_mnemonic: self.kanji_mnemonic,
-            spoken_mnemonic: self.spoken_mnemonic,
-            word_tenses: tenses
+            word: w.word,
+            is_kanji: w.is_kanji,
+            word_reading: w.word_reading,
+            definition: w.definition,
+            sentence: w.sentence,
+            sentence_translation: w.sentence_translation,
+            kanji_mnemonic: w.kanji_mnemonic,
+            spoken_mnemonic: w.spoken_mnemonic,
+            word_tenses: tenses,
+            created_by: None,
+            source: w.source.unwrap_or_default()
         }
     }
 }
 
-impl Into<NihongoWordTenseInsert> for NihongoWordReqTenseChatgpt {
-    fn into(self) -> NihongoWordTenseInsert {
+impl From<NihongoWordReqTenseChatgpt> for NihongoWordTenseInsert {
+    fn from(t: NihongoWordReqTenseChatgpt) -> Self {
         NihongoWordTenseInsert {
-            word: self.word,
-            sentence: self.sentence,
-            tense_type: self.tense_type
+            word: t.word,
+            sentence: t.sentence,
+            tense_type: t.tense_type
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NihongoWordUpdateReq {
+    pub word: Option<String>,
+    pub is_kanji: Option<bool>,
+    pub word_reading: Option<String>,
+    pub definition: Option<String>,
+    pub sentence: Option<String>,
+    pub sentence_translation: Option<String>,
+    /// `null` removes the mnemonic
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub kanji_mnemonic: Option<Option<String>>,
+    /// `null` removes the mnemonic
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    pub spoken_mnemonic: Option<Option<String>>
+}
+
+/// Tells a field sent as `null` (`Some(None)`) apart from one that's missing (`None`, through `default`)
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NihongoWordTenseUpdateReq {
+    pub word: Option<String>,
+    pub sentence: Option<String>,
+    pub tense_type: Option<String>
+}
+
+impl From<NihongoWordUpdateReq> for NihongoWordUpdate {
+    fn from(w: NihongoWordUpdateReq) -> Self {
+        NihongoWordUpdate {
+            word: w.word,
+            is_kanji: w.is_kanji,
+            word_reading: w.word_reading,
+            definition: w.definition,
+            sentence: w.sentence,
+            sentence_translation: w.sentence_translation,
+            kanji_mnemonic: w.kanji_mnemonic,
+            spoken_mnemonic: w.spoken_mnemonic
+        }
+    }
+}
+
+impl From<NihongoWordTenseUpdateReq> for NihongoWordTenseUpdate {
+    fn from(t: NihongoWordTenseUpdateReq) -> Self {
+        NihongoWordTenseUpdate {
+            word: t.word,
+            sentence: t.sentence,
+            tense_type: t.tense_type
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct NihongoWordSentenceReq {
+    pub sentence: String,
+    pub sentence_translation: String,
+    pub source: Option<String>
+}
+
+impl From<NihongoWordSentenceReq> for NihongoWordSentenceInsert {
+    fn from(s: NihongoWordSentenceReq) -> Self {
+        NihongoWordSentenceInsert {
+            sentence: s.sentence,
+            sentence_translation: s.sentence_translation,
+            source: s.source
         }
     }
 }
@@ -0,0 +1,79 @@
+use serde::{Serialize, Deserialize};
+use utoipa::ToSchema;
+
+use super::db::NihongoWordWithTensesStructured;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NihongoWordListRes {
+    pub words: Vec<NihongoWordWithTensesStructured>,
+    /// Number of words matching the filters, ignoring `limit` / `offset`
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum NihongoWordSaveStatus {
+    Created,
+    /// The word was already saved, nothing was changed
+    Duplicate,
+    /// The word was already saved and was overwritten or merged into, depending on `on_conflict`
+    Updated,
+    /// The submitted word didn't match the schema or failed validation
+    Invalid,
+    /// Generating or saving the word failed, `reason` says which
+    Failed
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NihongoWordSaveResult {
+    pub word: String,
+    pub status: NihongoWordSaveStatus,
+    /// Set unless the word is invalid or failed
+    pub id: Option<i64>,
+    /// Set for invalid and failed words
+    pub reason: Option<String>,
+    /// What validation corrected in the word, or flagged but saved anyway
+    #[serde(default)]
+    pub warnings: Vec<String>
+}
+
+/// Outcome of every word in a save request, in the order they were submitted (or extracted from the sentence)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NihongoWordSaveRes {
+    pub results: Vec<NihongoWordSaveResult>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    /// The LLM didn't return a usable response after retrying
+    LlmFailed,
+    Internal
+}
+
+/// Body of every non 2xx response: `{ "error": { "code": "not_found", "message": "Word not found" } }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorRes {
+    pub error: ErrorBody
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String
+}
+
+impl ErrorRes {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ErrorRes {
+            error: ErrorBody { code, message: message.into() }
+        }
+    }
+}
@@ -1 +1,3 @@
-pub mod data;
+pub mod store;
+pub mod postgres;
+pub mod sqlite;
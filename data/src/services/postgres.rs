@@ -0,0 +1,521 @@
+use sqlx::{Postgres, Transaction, postgres::{PgPool, PgPoolOptions}};
+use anyhow::Result;
+use async_trait::async_trait;
+use crate::models::db::{
+    NihongoWordWithTenses, NihongoWordTenseInsert, NihongoWordInsert, NihongoWordRow, NihongoWordFilter,
+    NihongoWordWithTensesStructured, NihongoWordWithTensesStructuredTenses, NihongoWordUpdate, NihongoWordTenseUpdate,
+    NihongoWordInsertOutcome, ConflictPolicy, NihongoWordSentence, NihongoWordSentenceInsert, group_tenses, group_sentences
+};
+use super::store::{WordStore, MergeTarget, merge_changes};
+
+#[derive(Clone, Debug)]
+pub struct PostgresDatabase {
+    pool: PgPool
+}
+
+impl PostgresDatabase {
+    pub async fn connect(db_url: &str, max_connections: u32) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(db_url)
+            .await?;
+
+        Ok(PostgresDatabase { pool })
+    }
+
+    async fn get_tenses(&self, word_ids: &[i64]) -> Result<Vec<NihongoWordWithTensesStructuredTenses>> {
+        let tenses = sqlx::query_as!(
+            NihongoWordWithTensesStructuredTenses,
+            r#"
+                SELECT id, word_id, word, sentence, tense_type, created_at
+                FROM nihongo_word_tense
+                WHERE word_id = ANY($1)
+                ORDER BY id
+            "#,
+            word_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(tenses)
+    }
+
+    async fn get_sentences(&self, word_ids: &[i64]) -> Result<Vec<NihongoWordSentence>> {
+        let sentences = sqlx::query_as!(
+            NihongoWordSentence,
+            r#"
+                SELECT id, word_id, sentence, sentence_translation, source, audio_status, created_at
+                FROM nihongo_word_sentence
+                WHERE word_id = ANY($1)
+                ORDER BY id
+            "#,
+            word_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sentences)
+    }
+}
+
+#[async_trait]
+impl WordStore for PostgresDatabase {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/postgres").run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn insert_word_with_tenses(&self, word: &NihongoWordInsert, on_conflict: ConflictPolicy) -> Result<NihongoWordInsertOutcome> {
+        let mut tx = self.pool.begin().await?;
+
+        let rec = sqlx::query!(
+                r#"
+                    INSERT INTO nihongo_word ( word, definition, sentence, kanji_mnemonic, spoken_mnemonic, word_reading, sentence_translation, is_kanji, created_by, source_title, source_url, source_location, seen_at )
+                    VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13 )
+                    ON CONFLICT ON CONSTRAINT uq_word DO NOTHING
+                    RETURNING id
+                "#,
+                word.word,
+                word.definition,
+                word.sentence,
+                word.kanji_mnemonic,
+                word.spoken_mnemonic,
+                word.word_reading,
+                word.sentence_translation,
+                word.is_kanji,
+                word.created_by,
+                word.source.title,
+                word.source.url,
+                word.source.location,
+                word.source.seen_at
+            )
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let outcome = match (rec, on_conflict) {
+            (Some(r), _) => {
+                if !word.word_tenses.is_empty() {
+                    insert_tenses(&mut tx, r.id, &word.word_tenses).await?;
+                }
+                NihongoWordInsertOutcome::Created(r.id)
+            },
+            (None, ConflictPolicy::Skip) => {
+                let id = sqlx::query_scalar!("SELECT id FROM nihongo_word WHERE word = $1", word.word)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                NihongoWordInsertOutcome::Duplicate(id)
+            },
+            (None, ConflictPolicy::Overwrite) => overwrite_word(&mut tx, word).await?,
+            (None, ConflictPolicy::Merge) => merge_word(&mut tx, word).await?
+        };
+
+        tx.commit().await?;
+
+        Ok(outcome)
+    }
+
+    async fn update_word_status(&self, id: i64) -> Result<()> {
+        sqlx::query!(
+                r#"
+                    UPDATE nihongo_word
+                    SET is_processed = true
+                    WHERE id = $1
+                "#,
+                id,
+            )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_unprocessed_words(&self) -> Result<Vec<NihongoWordWithTenses>> {
+        let res = sqlx::query_as!(
+            NihongoWordWithTenses,
+            r"
+                SELECT
+                    nw.id,
+                    nw.word,
+                    nw.is_kanji,
+                    nw.definition,
+                    nw.sentence,
+                    nw.kanji_mnemonic,
+                    nw.spoken_mnemonic,
+                    nw.word_reading,
+                    nw.sentence_translation,
+                    COALESCE(nwt.word_id, null) AS word_id,
+                    COALESCE(nwt.word, null) AS tense_word,
+                    COALESCE(nwt.sentence, null) AS tense_sentence,
+                    COALESCE(nwt.tense_type, null) AS tense_type,
+                    nw.source_title,
+                    nw.source_url
+                FROM nihongo_word AS nw
+                LEFT JOIN nihongo_word_tense AS nwt ON nw.id = nwt.word_id
+                WHERE nw.is_processed = false
+                ORDER BY nw.id DESC;
+            "
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn list_words(&self, filter: &NihongoWordFilter) -> Result<(Vec<NihongoWordWithTensesStructured>, i64)> {
+        let words = sqlx::query_as!(
+            NihongoWordRow,
+            r#"
+                SELECT
+                    nw.id,
+                    nw.word,
+                    nw.is_kanji,
+                    nw.word_reading,
+                    nw.definition,
+                    nw.sentence,
+                    nw.sentence_translation,
+                    nw.kanji_mnemonic,
+                    nw.spoken_mnemonic,
+                    nw.is_processed,
+                    nw.created_by,
+                    nw.source_title,
+                    nw.source_url,
+                    nw.source_location,
+                    nw.seen_at,
+                    nw.created_at
+                FROM nihongo_word AS nw
+                WHERE ( $1::bool IS NULL OR nw.is_processed = $1 )
+                    AND ( $2::bool IS NULL OR nw.is_kanji = $2 )
+                    AND ( $3::timestamptz IS NULL OR nw.created_at >= $3 )
+                    AND ( $4::timestamptz IS NULL OR nw.created_at < $4 )
+                    AND ( $5::text IS NULL OR strpos(nw.word, $5) > 0 OR strpos(nw.word_reading, $5) > 0 )
+                ORDER BY nw.id DESC
+                LIMIT $6 OFFSET $7
+            "#,
+            filter.is_processed,
+            filter.is_kanji,
+            filter.created_from,
+            filter.created_to,
+            filter.search,
+            filter.limit,
+            filter.offset
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total = sqlx::query_scalar!(
+            r#"
+                SELECT COUNT(*) AS "count!"
+                FROM nihongo_word AS nw
+                WHERE ( $1::bool IS NULL OR nw.is_processed = $1 )
+                    AND ( $2::bool IS NULL OR nw.is_kanji = $2 )
+                    AND ( $3::timestamptz IS NULL OR nw.created_at >= $3 )
+                    AND ( $4::timestamptz IS NULL OR nw.created_at < $4 )
+                    AND ( $5::text IS NULL OR strpos(nw.word, $5) > 0 OR strpos(nw.word_reading, $5) > 0 )
+            "#,
+            filter.is_processed,
+            filter.is_kanji,
+            filter.created_from,
+            filter.created_to,
+            filter.search
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        let ids = words.iter().map(|w| w.id).collect::<Vec<i64>>();
+        let tenses = self.get_tenses(&ids).await?;
+        let sentences = self.get_sentences(&ids).await?;
+
+        let mut words = group_tenses(words, tenses);
+        group_sentences(&mut words, sentences);
+
+        Ok((words, total))
+    }
+
+    async fn get_word(&self, id: i64) -> Result<Option<NihongoWordWithTensesStructured>> {
+        let word = sqlx::query_as!(
+            NihongoWordRow,
+            r#"
+                SELECT id, word, is_kanji, word_reading, definition, sentence, sentence_translation, kanji_mnemonic, spoken_mnemonic, is_processed, created_by,
+                    source_title, source_url, source_location, seen_at, created_at
+                FROM nihongo_word
+                WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match word {
+            Some(w) => {
+                let tenses = self.get_tenses(&[w.id]).await?;
+                let sentences = self.get_sentences(&[w.id]).await?;
+
+                let mut w = w.with_tenses(tenses);
+                w.sentences = sentences;
+                Ok(Some(w))
+            },
+            None => Ok(None)
+        }
+    }
+
+    async fn update_word(&self, id: i64, update: &NihongoWordUpdate) -> Result<bool> {
+        let res = sqlx::query!(
+                r#"
+                    UPDATE nihongo_word
+                    SET word = COALESCE($2, word),
+                        is_kanji = COALESCE($3, is_kanji),
+                        word_reading = COALESCE($4, word_reading),
+                        definition = COALESCE($5, definition),
+                        sentence = COALESCE($6, sentence),
+                        sentence_translation = COALESCE($7, sentence_translation),
+                        kanji_mnemonic = CASE WHEN $8 THEN $9 ELSE kanji_mnemonic END,
+                        spoken_mnemonic = CASE WHEN $10 THEN $11 ELSE spoken_mnemonic END,
+                        is_processed = false
+                    WHERE id = $1
+                "#,
+                id,
+                update.word,
+                update.is_kanji,
+                update.word_reading,
+                update.definition,
+                update.sentence,
+                update.sentence_translation,
+                update.kanji_mnemonic.is_some(),
+                update.kanji_mnemonic.clone().flatten(),
+                update.spoken_mnemonic.is_some(),
+                update.spoken_mnemonic.clone().flatten()
+            )
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_word(&self, id: i64) -> Result<bool> {
+        let res = sqlx::query!("DELETE FROM nihongo_word WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn update_word_tense(&self, word_id: i64, tense_id: i64, update: &NihongoWordTenseUpdate) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query!(
+                r#"
+                    UPDATE nihongo_word_tense
+                    SET word = COALESCE($3, word),
+                        sentence = COALESCE($4, sentence),
+                        tense_type = COALESCE($5, tense_type)
+                    WHERE id = $1 AND word_id = $2
+                "#,
+                tense_id,
+                word_id,
+                update.word,
+                update.sentence,
+                update.tense_type
+            )
+            .execute(&mut *tx)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        reset_word_status(&mut tx, word_id).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn delete_word_tense(&self, word_id: i64, tense_id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query!("DELETE FROM nihongo_word_tense WHERE id = $1 AND word_id = $2", tense_id, word_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        reset_word_status(&mut tx, word_id).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn add_word_sentence(&self, word_id: i64, sentence: &NihongoWordSentenceInsert) -> Result<Option<NihongoWordSentence>> {
+        let mut tx = self.pool.begin().await?;
+
+        let exists = sqlx::query_scalar!("SELECT id FROM nihongo_word WHERE id = $1 FOR UPDATE", word_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        if exists.is_none() {
+            return Ok(None);
+        }
+
+        let id = insert_sentence(&mut tx, word_id, sentence).await?;
+        reset_word_status(&mut tx, word_id).await?;
+
+        let sentence = sqlx::query_as!(
+                NihongoWordSentence,
+                r#"
+                    SELECT id, word_id, sentence, sentence_translation, source, audio_status, created_at
+                    FROM nihongo_word_sentence
+                    WHERE id = $1
+                "#,
+                id
+            )
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(sentence))
+    }
+
+    async fn get_word_sentences(&self, word_id: i64) -> Result<Vec<NihongoWordSentence>> {
+        self.get_sentences(&[word_id]).await
+    }
+
+    async fn update_sentence_audio_status(&self, id: i64, audio_status: &str) -> Result<()> {
+        sqlx::query!("UPDATE nihongo_word_sentence SET audio_status = $2 WHERE id = $1", id, audio_status)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps the id and `created_by` of the saved word, everything else including the tenses is replaced.
+/// The saved source is only replaced when the new word has one.
+async fn overwrite_word(tx: &mut Transaction<'_, Postgres>, word: &NihongoWordInsert) -> Result<NihongoWordInsertOutcome> {
+    let has_source = !word.source.is_empty();
+    let id = sqlx::query_scalar!(
+            r#"
+                UPDATE nihongo_word
+                SET definition = $2,
+                    sentence = $3,
+                    kanji_mnemonic = $4,
+                    spoken_mnemonic = $5,
+                    word_reading = $6,
+                    sentence_translation = $7,
+                    is_kanji = $8,
+                    source_title = CASE WHEN $9 THEN $10 ELSE source_title END,
+                    source_url = CASE WHEN $9 THEN $11 ELSE source_url END,
+                    source_location = CASE WHEN $9 THEN $12 ELSE source_location END,
+                    seen_at = CASE WHEN $9 THEN $13 ELSE seen_at END,
+                    is_processed = false
+                WHERE word = $1
+                RETURNING id
+            "#,
+            word.word,
+            word.definition,
+            word.sentence,
+            word.kanji_mnemonic,
+            word.spoken_mnemonic,
+            word.word_reading,
+            word.sentence_translation,
+            word.is_kanji,
+            has_source,
+            word.source.title,
+            word.source.url,
+            word.source.location,
+            word.source.seen_at
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+    sqlx::query!("DELETE FROM nihongo_word_tense WHERE word_id = $1", id)
+        .execute(&mut **tx)
+        .await?;
+
+    if !word.word_tenses.is_empty() {
+        insert_tenses(tx, id, &word.word_tenses).await?;
+    }
+
+    Ok(NihongoWordInsertOutcome::Updated(id))
+}
+
+async fn merge_word(tx: &mut Transaction<'_, Postgres>, word: &NihongoWordInsert) -> Result<NihongoWordInsertOutcome> {
+    let saved = sqlx::query!("SELECT id, sentence FROM nihongo_word WHERE word = $1 FOR UPDATE", word.word)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    let mut sentences = sqlx::query_scalar!("SELECT sentence FROM nihongo_word_sentence WHERE word_id = $1", saved.id)
+        .fetch_all(&mut **tx)
+        .await?;
+    sentences.push(saved.sentence);
+
+    let tense_types = sqlx::query_scalar!("SELECT tense_type FROM nihongo_word_tense WHERE word_id = $1", saved.id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+    let changes = merge_changes(&MergeTarget { sentences, tense_types }, word);
+
+    if changes.is_empty() {
+        return Ok(NihongoWordInsertOutcome::Duplicate(saved.id));
+    }
+
+    if let Some(sentence) = &changes.sentence {
+        insert_sentence(tx, saved.id, sentence).await?;
+    }
+
+    if !changes.tenses.is_empty() {
+        insert_tenses(tx, saved.id, &changes.tenses).await?;
+    }
+
+    reset_word_status(tx, saved.id).await?;
+
+    Ok(NihongoWordInsertOutcome::Updated(saved.id))
+}
+
+async fn insert_sentence(tx: &mut Transaction<'_, Postgres>, word_id: i64, sentence: &NihongoWordSentenceInsert) -> Result<i64> {
+    let id = sqlx::query_scalar!(
+            r#"
+                INSERT INTO nihongo_word_sentence ( word_id, sentence, sentence_translation, source )
+                VALUES ( $1, $2, $3, $4 )
+                RETURNING id
+            "#,
+            word_id,
+            sentence.sentence,
+            sentence.sentence_translation,
+            sentence.source
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+
+    Ok(id)
+}
+
+async fn reset_word_status(tx: &mut Transaction<'_, Postgres>, word_id: i64) -> Result<()> {
+    sqlx::query!("UPDATE nihongo_word SET is_processed = false WHERE id = $1", word_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+async fn insert_tenses(tx: &mut Transaction<'_, Postgres>, word_id: i64, tenses: &[NihongoWordTenseInsert]) -> Result<()> {
+    let words = tenses.iter().map(|t| t.word.clone()).collect::<Vec<String>>();
+    let sentences = tenses.iter().map(|t| t.sentence.clone()).collect::<Vec<String>>();
+    let tense_types = tenses.iter().map(|t| t.tense_type.clone()).collect::<Vec<String>>();
+
+    sqlx::query!(
+            r#"
+                INSERT INTO nihongo_word_tense ( word_id, word, sentence, tense_type )
+                SELECT $1, * FROM UNNEST( $2::text[], $3::text[], $4::text[] )
+            "#,
+            word_id,
+            &words,
+            &sentences,
+            &tense_types
+        )
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,823 @@
+use std::str::FromStr;
+use sqlx::{QueryBuilder, Sqlite, Transaction, sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions}};
+use anyhow::Result;
+use async_trait::async_trait;
+use sqlx::types::chrono::{DateTime, Utc};
+use crate::models::db::{
+    NihongoWordWithTenses, NihongoWordTenseInsert, NihongoWordInsert, NihongoWordRow, NihongoWordFilter,
+    NihongoWordWithTensesStructured, NihongoWordWithTensesStructuredTenses, NihongoWordUpdate, NihongoWordTenseUpdate,
+    NihongoWordInsertOutcome, ConflictPolicy, NihongoWordSentence, NihongoWordSentenceInsert, group_tenses, group_sentences
+};
+use super::store::{WordStore, MergeTarget, merge_changes};
+
+/// Local file database for running the api, worker and anki on one machine.
+/// Queries aren't checked at compile time here since `DATABASE_URL` points at postgres for the `query!` macros.
+#[derive(Clone, Debug)]
+pub struct SqliteDatabase {
+    pool: SqlitePool
+}
+
+impl SqliteDatabase {
+    pub async fn connect(db_url: &str, max_connections: u32) -> Result<Self> {
+        let pool = pool_options(db_url, max_connections)
+            .connect_with(connect_options(db_url)?)
+            .await?;
+
+        Ok(SqliteDatabase { pool })
+    }
+
+    async fn get_tenses(&self, word_ids: &[i64]) -> Result<Vec<NihongoWordWithTensesStructuredTenses>> {
+        if word_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT id, word_id, word, sentence, tense_type, created_at FROM nihongo_word_tense WHERE word_id IN (");
+        let mut ids = query.separated(", ");
+        for id in word_ids {
+            ids.push_bind(*id);
+        }
+        query.push(") ORDER BY id");
+
+        Ok(query.build_query_as().fetch_all(&self.pool).await?)
+    }
+
+    async fn get_sentences(&self, word_ids: &[i64]) -> Result<Vec<NihongoWordSentence>> {
+        if word_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM nihongo_word_sentence WHERE word_id IN (", SENTENCE_COLUMNS));
+        let mut ids = query.separated(", ");
+        for id in word_ids {
+            ids.push_bind(*id);
+        }
+        query.push(") ORDER BY id");
+
+        Ok(query.build_query_as().fetch_all(&self.pool).await?)
+    }
+}
+
+const WORD_COLUMNS: &str = "nw.id, nw.word, nw.is_kanji, nw.word_reading, nw.definition, nw.sentence, nw.sentence_translation, nw.kanji_mnemonic, nw.spoken_mnemonic, nw.is_processed, nw.created_by, nw.source_title, nw.source_url, nw.source_location, nw.seen_at, nw.created_at";
+
+const SENTENCE_COLUMNS: &str = "id, word_id, sentence, sentence_translation, source, audio_status, created_at";
+
+/// Timestamps are stored as text in the format of the `created_at` default so they compare correctly
+fn to_sqlite_timestamp(d: &DateTime<Utc>) -> String {
+    d.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &NihongoWordFilter) {
+    query.push(" WHERE 1 = 1");
+
+    if let Some(p) = filter.is_processed {
+        query.push(" AND nw.is_processed = ").push_bind(p);
+    }
+    if let Some(k) = filter.is_kanji {
+        query.push(" AND nw.is_kanji = ").push_bind(k);
+    }
+    if let Some(from) = &filter.created_from {
+        query.push(" AND nw.created_at >= ").push_bind(to_sqlite_timestamp(from));
+    }
+    if let Some(to) = &filter.created_to {
+        query.push(" AND nw.created_at < ").push_bind(to_sqlite_timestamp(to));
+    }
+    if let Some(search) = &filter.search {
+        query.push(" AND ( instr(nw.word, ")
+            .push_bind(search.clone())
+            .push(") > 0 OR instr(nw.word_reading, ")
+            .push_bind(search.clone())
+            .push(") > 0 )");
+    }
+}
+
+fn connect_options(db_url: &str) -> Result<SqliteConnectOptions> {
+    Ok(SqliteConnectOptions::from_str(db_url)?
+        .create_if_missing(true)
+        .foreign_keys(true))
+}
+
+fn pool_options(db_url: &str, max_connections: u32) -> SqlitePoolOptions {
+    // every connection to `sqlite::memory:` is its own database, so keep exactly one alive for the pool's lifetime
+    if db_url.contains(":memory:") {
+        return SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None);
+    }
+
+    SqlitePoolOptions::new().max_connections(max_connections)
+}
+
+#[async_trait]
+impl WordStore for SqliteDatabase {
+    async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations/sqlite").run(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn insert_word_with_tenses(&self, word: &NihongoWordInsert, on_conflict: ConflictPolicy) -> Result<NihongoWordInsertOutcome> {
+        let mut tx = self.pool.begin().await?;
+
+        let id: Option<i64> = sqlx::query_scalar(
+                r#"
+                    INSERT INTO nihongo_word ( word, definition, sentence, kanji_mnemonic, spoken_mnemonic, word_reading, sentence_translation, is_kanji, created_by, source_title, source_url, source_location, seen_at )
+                    VALUES ( ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ? )
+                    ON CONFLICT ( word ) DO NOTHING
+                    RETURNING id
+                "#
+            )
+            .bind(&word.word)
+            .bind(&word.definition)
+            .bind(&word.sentence)
+            .bind(&word.kanji_mnemonic)
+            .bind(&word.spoken_mnemonic)
+            .bind(&word.word_reading)
+            .bind(&word.sentence_translation)
+            .bind(word.is_kanji)
+            .bind(&word.created_by)
+            .bind(&word.source.title)
+            .bind(&word.source.url)
+            .bind(&word.source.location)
+            .bind(word.source.seen_at.as_ref().map(to_sqlite_timestamp))
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        let outcome = match (id, on_conflict) {
+            (Some(id), _) => {
+                if !word.word_tenses.is_empty() {
+                    insert_tenses(&mut tx, id, &word.word_tenses).await?;
+                }
+                NihongoWordInsertOutcome::Created(id)
+            },
+            (None, ConflictPolicy::Skip) => {
+                let id = sqlx::query_scalar("SELECT id FROM nihongo_word WHERE word = ?")
+                    .bind(&word.word)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                NihongoWordInsertOutcome::Duplicate(id)
+            },
+            (None, ConflictPolicy::Overwrite) => overwrite_word(&mut tx, word).await?,
+            (None, ConflictPolicy::Merge) => merge_word(&mut tx, word).await?
+        };
+
+        tx.commit().await?;
+
+        Ok(outcome)
+    }
+
+    async fn update_word_status(&self, id: i64) -> Result<()> {
+        sqlx::query(
+                r#"
+                    UPDATE nihongo_word
+                    SET is_processed = true
+                    WHERE id = ?
+                "#
+            )
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn get_unprocessed_words(&self) -> Result<Vec<NihongoWordWithTenses>> {
+        let res = sqlx::query_as::<_, NihongoWordWithTenses>(
+            r"
+                SELECT
+                    nw.id,
+                    nw.word,
+                    nw.is_kanji,
+                    nw.definition,
+                    nw.sentence,
+                    nw.kanji_mnemonic,
+                    nw.spoken_mnemonic,
+                    nw.word_reading,
+                    nw.sentence_translation,
+                    nwt.word_id AS word_id,
+                    nwt.word AS tense_word,
+                    nwt.sentence AS tense_sentence,
+                    nwt.tense_type AS tense_type,
+                    nw.source_title,
+                    nw.source_url
+                FROM nihongo_word AS nw
+                LEFT JOIN nihongo_word_tense AS nwt ON nw.id = nwt.word_id
+                WHERE nw.is_processed = false
+                ORDER BY nw.id DESC;
+            "
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(res)
+    }
+
+    async fn list_words(&self, filter: &NihongoWordFilter) -> Result<(Vec<NihongoWordWithTensesStructured>, i64)> {
+        let mut query = QueryBuilder::<Sqlite>::new(format!("SELECT {} FROM nihongo_word AS nw", WORD_COLUMNS));
+        push_filter(&mut query, filter);
+        query.push(" ORDER BY nw.id DESC LIMIT ")
+            .push_bind(filter.limit)
+            .push(" OFFSET ")
+            .push_bind(filter.offset);
+
+        let words = query.build_query_as::<NihongoWordRow>()
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut query = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM nihongo_word AS nw");
+        push_filter(&mut query, filter);
+
+        let total: i64 = query.build_query_scalar()
+            .fetch_one(&self.pool)
+            .await?;
+
+        let ids = words.iter().map(|w| w.id).collect::<Vec<i64>>();
+        let tenses = self.get_tenses(&ids).await?;
+        let sentences = self.get_sentences(&ids).await?;
+
+        let mut words = group_tenses(words, tenses);
+        group_sentences(&mut words, sentences);
+
+        Ok((words, total))
+    }
+
+    async fn get_word(&self, id: i64) -> Result<Option<NihongoWordWithTensesStructured>> {
+        let word = sqlx::query_as::<_, NihongoWordRow>(&format!("SELECT {} FROM nihongo_word AS nw WHERE nw.id = ?", WORD_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match word {
+            Some(w) => {
+                let tenses = self.get_tenses(&[w.id]).await?;
+                let sentences = self.get_sentences(&[w.id]).await?;
+
+                let mut w = w.with_tenses(tenses);
+                w.sentences = sentences;
+                Ok(Some(w))
+            },
+            None => Ok(None)
+        }
+    }
+
+    async fn update_word(&self, id: i64, update: &NihongoWordUpdate) -> Result<bool> {
+        let res = sqlx::query(
+                r#"
+                    UPDATE nihongo_word
+                    SET word = COALESCE(?, word),
+                        is_kanji = COALESCE(?, is_kanji),
+                        word_reading = COALESCE(?, word_reading),
+                        definition = COALESCE(?, definition),
+                        sentence = COALESCE(?, sentence),
+                        sentence_translation = COALESCE(?, sentence_translation),
+                        kanji_mnemonic = CASE WHEN ? THEN ? ELSE kanji_mnemonic END,
+                        spoken_mnemonic = CASE WHEN ? THEN ? ELSE spoken_mnemonic END,
+                        is_processed = false
+                    WHERE id = ?
+                "#
+            )
+            .bind(&update.word)
+            .bind(update.is_kanji)
+            .bind(&update.word_reading)
+            .bind(&update.definition)
+            .bind(&update.sentence)
+            .bind(&update.sentence_translation)
+            .bind(update.kanji_mnemonic.is_some())
+            .bind(update.kanji_mnemonic.clone().flatten())
+            .bind(update.spoken_mnemonic.is_some())
+            .bind(update.spoken_mnemonic.clone().flatten())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn delete_word(&self, id: i64) -> Result<bool> {
+        let res = sqlx::query("DELETE FROM nihongo_word WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(res.rows_affected() > 0)
+    }
+
+    async fn update_word_tense(&self, word_id: i64, tense_id: i64, update: &NihongoWordTenseUpdate) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query(
+                r#"
+                    UPDATE nihongo_word_tense
+                    SET word = COALESCE(?, word),
+                        sentence = COALESCE(?, sentence),
+                        tense_type = COALESCE(?, tense_type)
+                    WHERE id = ? AND word_id = ?
+                "#
+            )
+            .bind(&update.word)
+            .bind(&update.sentence)
+            .bind(&update.tense_type)
+            .bind(tense_id)
+            .bind(word_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        reset_word_status(&mut tx, word_id).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn delete_word_tense(&self, word_id: i64, tense_id: i64) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query("DELETE FROM nihongo_word_tense WHERE id = ? AND word_id = ?")
+            .bind(tense_id)
+            .bind(word_id)
+            .execute(&mut *tx)
+            .await?;
+
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        reset_word_status(&mut tx, word_id).await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    async fn add_word_sentence(&self, word_id: i64, sentence: &NihongoWordSentenceInsert) -> Result<Option<NihongoWordSentence>> {
+        let mut tx = self.pool.begin().await?;
+
+        let exists: Option<i64> = sqlx::query_scalar("SELECT id FROM nihongo_word WHERE id = ?")
+            .bind(word_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+        if exists.is_none() {
+            return Ok(None);
+        }
+
+        let id = insert_sentence(&mut tx, word_id, sentence).await?;
+        reset_word_status(&mut tx, word_id).await?;
+
+        let sentence = sqlx::query_as::<_, NihongoWordSentence>(&format!("SELECT {} FROM nihongo_word_sentence WHERE id = ?", SENTENCE_COLUMNS))
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(Some(sentence))
+    }
+
+    async fn get_word_sentences(&self, word_id: i64) -> Result<Vec<NihongoWordSentence>> {
+        self.get_sentences(&[word_id]).await
+    }
+
+    async fn update_sentence_audio_status(&self, id: i64, audio_status: &str) -> Result<()> {
+        sqlx::query("UPDATE nihongo_word_sentence SET audio_status = ? WHERE id = ?")
+            .bind(audio_status)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps the id and `created_by` of the saved word, everything else including the tenses is replaced.
+/// The saved source is only replaced when the new word has one.
+async fn overwrite_word(tx: &mut Transaction<'_, Sqlite>, word: &NihongoWordInsert) -> Result<NihongoWordInsertOutcome> {
+    let has_source = !word.source.is_empty();
+    let id: i64 = sqlx::query_scalar(
+            r#"
+                UPDATE nihongo_word
+                SET definition = ?,
+                    sentence = ?,
+                    kanji_mnemonic = ?,
+                    spoken_mnemonic = ?,
+                    word_reading = ?,
+                    sentence_translation = ?,
+                    is_kanji = ?,
+                    source_title = CASE WHEN ? THEN ? ELSE source_title END,
+                    source_url = CASE WHEN ? THEN ? ELSE source_url END,
+                    source_location = CASE WHEN ? THEN ? ELSE source_location END,
+                    seen_at = CASE WHEN ? THEN ? ELSE seen_at END,
+                    is_processed = false
+                WHERE word = ?
+                RETURNING id
+            "#
+        )
+        .bind(&word.definition)
+        .bind(&word.sentence)
+        .bind(&word.kanji_mnemonic)
+        .bind(&word.spoken_mnemonic)
+        .bind(&word.word_reading)
+        .bind(&word.sentence_translation)
+        .bind(word.is_kanji)
+        .bind(has_source)
+        .bind(&word.source.title)
+        .bind(has_source)
+        .bind(&word.source.url)
+        .bind(has_source)
+        .bind(&word.source.location)
+        .bind(has_source)
+        .bind(word.source.seen_at.as_ref().map(to_sqlite_timestamp))
+        .bind(&word.word)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    sqlx::query("DELETE FROM nihongo_word_tense WHERE word_id = ?")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+
+    if !word.word_tenses.is_empty() {
+        insert_tenses(tx, id, &word.word_tenses).await?;
+    }
+
+    Ok(NihongoWordInsertOutcome::Updated(id))
+}
+
+async fn merge_word(tx: &mut Transaction<'_, Sqlite>, word: &NihongoWordInsert) -> Result<NihongoWordInsertOutcome> {
+    let (id, sentence): (i64, String) = sqlx::query_as("SELECT id, sentence FROM nihongo_word WHERE word = ?")
+        .bind(&word.word)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    let mut sentences: Vec<String> = sqlx::query_scalar("SELECT sentence FROM nihongo_word_sentence WHERE word_id = ?")
+        .bind(id)
+        .fetch_all(&mut **tx)
+        .await?;
+    sentences.push(sentence);
+
+    let tense_types: Vec<String> = sqlx::query_scalar("SELECT tense_type FROM nihongo_word_tense WHERE word_id = ?")
+        .bind(id)
+        .fetch_all(&mut **tx)
+        .await?;
+
+    let changes = merge_changes(&MergeTarget { sentences, tense_types }, word);
+
+    if changes.is_empty() {
+        return Ok(NihongoWordInsertOutcome::Duplicate(id));
+    }
+
+    if let Some(sentence) = &changes.sentence {
+        insert_sentence(tx, id, sentence).await?;
+    }
+
+    if !changes.tenses.is_empty() {
+        insert_tenses(tx, id, &changes.tenses).await?;
+    }
+
+    reset_word_status(tx, id).await?;
+
+    Ok(NihongoWordInsertOutcome::Updated(id))
+}
+
+async fn insert_sentence(tx: &mut Transaction<'_, Sqlite>, word_id: i64, sentence: &NihongoWordSentenceInsert) -> Result<i64> {
+    let id = sqlx::query_scalar(
+            r#"
+                INSERT INTO nihongo_word_sentence ( word_id, sentence, sentence_translation, source )
+                VALUES ( ?, ?, ?, ? )
+                RETURNING id
+            "#
+        )
+        .bind(word_id)
+        .bind(&sentence.sentence)
+        .bind(&sentence.sentence_translation)
+        .bind(&sentence.source)
+        .fetch_one(&mut **tx)
+        .await?;
+
+    Ok(id)
+}
+
+async fn reset_word_status(tx: &mut Transaction<'_, Sqlite>, word_id: i64) -> Result<()> {
+    sqlx::query("UPDATE nihongo_word SET is_processed = false WHERE id = ?")
+        .bind(word_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+async fn insert_tenses(tx: &mut Transaction<'_, Sqlite>, word_id: i64, tenses: &[NihongoWordTenseInsert]) -> Result<()> {
+    let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO nihongo_word_tense ( word_id, word, sentence, tense_type ) ");
+    query.push_values(tenses, |mut b, t| {
+        b.push_bind(word_id)
+            .push_bind(&t.word)
+            .push_bind(&t.sentence)
+            .push_bind(&t.tense_type);
+    });
+
+    query.build().execute(&mut **tx).await?;
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::db::NihongoWordSource;
+
+    async fn db() -> SqliteDatabase {
+        let db = SqliteDatabase::connect("sqlite::memory:", 1).await.unwrap();
+        db.migrate().await.unwrap();
+        db
+    }
+
+    async fn insert(db: &SqliteDatabase, word: &NihongoWordInsert) -> i64 {
+        match db.insert_word_with_tenses(word, ConflictPolicy::Skip).await.unwrap() {
+            NihongoWordInsertOutcome::Created(id) => id,
+            outcome => panic!("Expected {} to be created, got {:?}", word.word, outcome)
+        }
+    }
+
+    fn word(word: &str, tenses: &[(&str, &str)]) -> NihongoWordInsert {
+        NihongoWordInsert {
+            word: word.to_string(),
+            is_kanji: true,
+            word_reading: "たべる".to_string(),
+            definition: "to eat".to_string(),
+            sentence: "毎朝パンを食べる。".to_string(),
+            sentence_translation: "I eat bread every morning.".to_string(),
+            kanji_mnemonic: None,
+            spoken_mnemonic: Some("Tabby cat eats".to_string()),
+            word_tenses: tenses.iter()
+                .map(|(w, t)| NihongoWordTenseInsert { word: w.to_string(), sentence: format!("{}。", w), tense_type: t.to_string() })
+                .collect(),
+            created_by: None,
+            source: Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn inserts_word_with_tenses() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[("食べた", "past"), ("食べない", "negative")])).await;
+
+        let rows = db.get_unprocessed_words().await.unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|r| r.word_id == Some(id) && r.is_kanji));
+        assert_eq!(rows[0].spoken_mnemonic.as_deref(), Some("Tabby cat eats"));
+    }
+
+    #[tokio::test]
+    async fn duplicate_word_is_skipped() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[])).await;
+        let outcome = db.insert_word_with_tenses(&word("食べる", &[("食べた", "past")]), ConflictPolicy::Skip).await.unwrap();
+        assert_eq!(outcome, NihongoWordInsertOutcome::Duplicate(id));
+
+        // the duplicate's tenses must not have been attached to anything
+        let rows = db.get_unprocessed_words().await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].tense_word, None);
+    }
+
+    #[tokio::test]
+    async fn processed_words_are_not_returned() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[])).await;
+        insert(&db, &word("飲む", &[])).await;
+        db.update_word_status(id).await.unwrap();
+
+        let rows = db.get_unprocessed_words().await.unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].word, "飲む");
+    }
+
+    #[tokio::test]
+    async fn lists_words_with_filters() {
+        let db = db().await;
+
+        let eat = insert(&db, &word("食べる", &[("食べた", "past")])).await;
+        let mut sugoi = word("すごい", &[]);
+        sugoi.is_kanji = false;
+        sugoi.word_reading = "すごい".to_string();
+        insert(&db, &sugoi).await;
+        db.update_word_status(eat).await.unwrap();
+
+        let (words, total) = db.list_words(&NihongoWordFilter::default()).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(words[0].word, "すごい");
+        assert_eq!(words[1].word_tenses.len(), 1);
+
+        let (words, total) = db.list_words(&NihongoWordFilter { is_processed: Some(false), ..Default::default() }).await.unwrap();
+        assert_eq!((words.len(), total), (1, 1));
+        assert_eq!(words[0].word, "すごい");
+
+        let (words, _) = db.list_words(&NihongoWordFilter { is_kanji: Some(true), ..Default::default() }).await.unwrap();
+        assert_eq!(words[0].id, eat);
+
+        let (words, _) = db.list_words(&NihongoWordFilter { search: Some("たべ".to_string()), ..Default::default() }).await.unwrap();
+        assert_eq!(words[0].id, eat);
+
+        let (words, total) = db.list_words(&NihongoWordFilter { limit: 1, offset: 1, ..Default::default() }).await.unwrap();
+        assert_eq!((words.len(), total), (1, 2));
+        assert_eq!(words[0].id, eat);
+
+        let tomorrow = Utc::now() + chrono::Duration::days(1);
+        let (words, _) = db.list_words(&NihongoWordFilter { created_from: Some(tomorrow), ..Default::default() }).await.unwrap();
+        assert!(words.is_empty());
+        let (words, _) = db.list_words(&NihongoWordFilter { created_to: Some(tomorrow), ..Default::default() }).await.unwrap();
+        assert_eq!(words.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn gets_word_with_tenses() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[("食べた", "past"), ("食べない", "negative")])).await;
+
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert_eq!(w.word, "食べる");
+        assert!(!w.is_processed);
+        assert_eq!(w.created_by, None);
+        assert_eq!(w.word_tenses.iter().map(|t| t.tense_type.as_str()).collect::<Vec<&str>>(), vec!["past", "negative"]);
+
+        assert!(db.get_word(id + 1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn update_resets_processed() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[])).await;
+        db.update_word_status(id).await.unwrap();
+
+        let update = NihongoWordUpdate { definition: Some("to eat; to live on".to_string()), ..Default::default() };
+        assert!(db.update_word(id, &update).await.unwrap());
+        assert!(!db.update_word(id + 1, &update).await.unwrap());
+
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert_eq!(w.definition, "to eat; to live on");
+        assert_eq!(w.word_reading, "たべる");
+        assert_eq!(w.spoken_mnemonic.as_deref(), Some("Tabby cat eats"));
+        assert!(!w.is_processed);
+
+        let clear = NihongoWordUpdate { spoken_mnemonic: Some(None), ..Default::default() };
+        assert!(db.update_word(id, &clear).await.unwrap());
+        assert_eq!(db.get_word(id).await.unwrap().unwrap().spoken_mnemonic, None);
+    }
+
+    #[tokio::test]
+    async fn renaming_to_existing_word_is_unique_violation() {
+        let db = db().await;
+
+        insert(&db, &word("食べる", &[])).await;
+        let id = insert(&db, &word("飲む", &[])).await;
+
+        let err = db.update_word(id, &NihongoWordUpdate { word: Some("食べる".to_string()), ..Default::default() }).await.unwrap_err();
+        assert!(crate::services::store::is_unique_violation(&err));
+    }
+
+    #[tokio::test]
+    async fn updates_and_deletes_tenses() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[("食べた", "past"), ("食べない", "negative")])).await;
+        let other = insert(&db, &word("飲む", &[])).await;
+        let tenses = db.get_word(id).await.unwrap().unwrap().word_tenses;
+        db.update_word_status(id).await.unwrap();
+
+        let update = NihongoWordTenseUpdate { sentence: Some("昨日寿司を食べた。".to_string()), ..Default::default() };
+        // tense has to belong to the word in the path
+        assert!(!db.update_word_tense(other, tenses[0].id, &update).await.unwrap());
+        assert!(db.get_word(id).await.unwrap().unwrap().is_processed);
+
+        assert!(db.update_word_tense(id, tenses[0].id, &update).await.unwrap());
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert!(!w.is_processed);
+        assert_eq!(w.word_tenses[0].sentence, "昨日寿司を食べた。");
+        assert_eq!(w.word_tenses[0].tense_type, "past");
+
+        db.update_word_status(id).await.unwrap();
+        assert!(db.delete_word_tense(id, tenses[1].id).await.unwrap());
+        assert!(!db.delete_word_tense(id, tenses[1].id).await.unwrap());
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert!(!w.is_processed);
+        assert_eq!(w.word_tenses.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn delete_word_cascades_to_tenses() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[("食べた", "past")])).await;
+        assert!(db.delete_word(id).await.unwrap());
+        assert!(!db.delete_word(id).await.unwrap());
+        assert!(db.get_word(id).await.unwrap().is_none());
+        assert!(db.get_tenses(&[id]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn overwrites_existing_word() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[("食べた", "past"), ("食べない", "negative")])).await;
+        db.update_word_status(id).await.unwrap();
+
+        let mut new = word("食べる", &[("食べます", "polite")]);
+        new.definition = "to eat; to live on".to_string();
+        let outcome = db.insert_word_with_tenses(&new, ConflictPolicy::Overwrite).await.unwrap();
+        assert_eq!(outcome, NihongoWordInsertOutcome::Updated(id));
+
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert_eq!(w.definition, "to eat; to live on");
+        assert!(!w.is_processed);
+        assert_eq!(w.word_tenses.iter().map(|t| t.tense_type.as_str()).collect::<Vec<&str>>(), vec!["polite"]);
+    }
+
+    #[tokio::test]
+    async fn merges_into_existing_word() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[("食べた", "past")])).await;
+        db.update_word_status(id).await.unwrap();
+
+        // same sentence and tense type, nothing to merge
+        let outcome = db.insert_word_with_tenses(&word("食べる", &[("食べた", "past")]), ConflictPolicy::Merge).await.unwrap();
+        assert_eq!(outcome, NihongoWordInsertOutcome::Duplicate(id));
+        assert!(db.get_word(id).await.unwrap().unwrap().is_processed);
+
+        let mut new = word("食べる", &[("食べた", "past"), ("食べない", "negative")]);
+        new.sentence = "寿司を食べる。".to_string();
+        new.sentence_translation = "I eat sushi.".to_string();
+        new.definition = "ignored when merging".to_string();
+        let outcome = db.insert_word_with_tenses(&new, ConflictPolicy::Merge).await.unwrap();
+        assert_eq!(outcome, NihongoWordInsertOutcome::Updated(id));
+
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert!(!w.is_processed);
+        assert_eq!(w.definition, "to eat");
+        assert_eq!(w.sentence, "毎朝パンを食べる。");
+        assert_eq!(w.sentences.iter().map(|s| (s.sentence.as_str(), s.sentence_translation.as_str())).collect::<Vec<(&str, &str)>>(), vec![("寿司を食べる。", "I eat sushi.")]);
+        assert_eq!(w.word_tenses.iter().map(|t| t.tense_type.as_str()).collect::<Vec<&str>>(), vec!["past", "negative"]);
+    }
+
+    #[tokio::test]
+    async fn adds_sentences_to_word() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[])).await;
+        db.update_word_status(id).await.unwrap();
+
+        let sentence = NihongoWordSentenceInsert {
+            sentence: "寿司を食べる。".to_string(),
+            sentence_translation: "I eat sushi.".to_string(),
+            source: Some("よつばと！ 1巻".to_string())
+        };
+        assert!(db.add_word_sentence(id + 1, &sentence).await.unwrap().is_none());
+
+        let added = db.add_word_sentence(id, &sentence).await.unwrap().unwrap();
+        assert_eq!(added.word_id, id);
+        assert_eq!(added.audio_status, "pending");
+        assert_eq!(added.source.as_deref(), Some("よつばと！ 1巻"));
+
+        let w = db.get_word(id).await.unwrap().unwrap();
+        assert!(!w.is_processed);
+        assert_eq!(w.sentences, vec![added.clone()]);
+
+        db.update_sentence_audio_status(added.id, "generated").await.unwrap();
+        assert_eq!(db.get_word_sentences(id).await.unwrap()[0].audio_status, "generated");
+        assert!(db.update_sentence_audio_status(added.id, "done").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn saves_word_source() {
+        let db = db().await;
+
+        let id = insert(&db, &word("食べる", &[])).await;
+        assert_eq!(db.get_word(id).await.unwrap().unwrap().source, None);
+
+        let source = NihongoWordSource {
+            title: Some("NHK Easy".to_string()),
+            url: Some("https://www3.nhk.or.jp/news/easy/".to_string()),
+            location: None,
+            seen_at: Some("2024-04-01T09:30:00Z".parse().unwrap())
+        };
+        let mut new = word("食べる", &[]);
+        new.source = source.clone();
+        db.insert_word_with_tenses(&new, ConflictPolicy::Overwrite).await.unwrap();
+        assert_eq!(db.get_word(id).await.unwrap().unwrap().source, Some(source.clone()));
+
+        // overwriting without a source keeps the saved one
+        db.insert_word_with_tenses(&word("食べる", &[]), ConflictPolicy::Overwrite).await.unwrap();
+        assert_eq!(db.get_word(id).await.unwrap().unwrap().source, Some(source));
+
+        let rows = db.get_unprocessed_words().await.unwrap();
+        assert_eq!(rows[0].source_title.as_deref(), Some("NHK Easy"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashSet, sync::Arc};
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use dotenv::dotenv;
+
+use crate::models::db::{
+    NihongoWordWithTenses, NihongoWordInsert, NihongoWordWithTensesStructured, NihongoWordFilter,
+    NihongoWordUpdate, NihongoWordTenseUpdate, NihongoWordTenseInsert, NihongoWordInsertOutcome, ConflictPolicy,
+    NihongoWordSentence, NihongoWordSentenceInsert
+};
+use super::{postgres::PostgresDatabase, sqlite::SqliteDatabase};
+
+// lambda spins up a container (and so a pool) per concurrent request, keep this low
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 2;
+
+/// Every operation the api and worker need from the database, implemented for postgres and sqlite
+#[async_trait]
+pub trait WordStore: Send + Sync + std::fmt::Debug {
+    /// Applies the migrations embedded from `data/migrations/<backend>`, already applied ones are skipped
+    async fn migrate(&self) -> Result<()>;
+
+    /// Inserts the word and all of its tenses in one transaction, either everything is saved or nothing is.
+    /// When the word already exists (`uq_word`) `on_conflict` decides whether it's skipped, overwritten or merged into.
+    async fn insert_word_with_tenses(&self, word: &NihongoWordInsert, on_conflict: ConflictPolicy) -> Result<NihongoWordInsertOutcome>;
+
+    async fn update_word_status(&self, id: i64) -> Result<()>;
+
+    async fn get_unprocessed_words(&self) -> Result<Vec<NihongoWordWithTenses>>;
+
+    /// Returns the requested page (newest first) and the total number of words matching the filter
+    async fn list_words(&self, filter: &NihongoWordFilter) -> Result<(Vec<NihongoWordWithTensesStructured>, i64)>;
+
+    async fn get_word(&self, id: i64) -> Result<Option<NihongoWordWithTensesStructured>>;
+
+    /// Applies the update and resets `is_processed` so the worker pushes the corrected note to anki again.
+    /// Returns `false` when the word doesn't exist.
+    async fn update_word(&self, id: i64, update: &NihongoWordUpdate) -> Result<bool>;
+
+    /// Deletes the word and (cascading) its tenses, returns `false` when the word doesn't exist
+    async fn delete_word(&self, id: i64) -> Result<bool>;
+
+    /// Applies the update and resets `is_processed` on the tense's word.
+    /// Returns `false` when the tense doesn't exist or doesn't belong to the word.
+    async fn update_word_tense(&self, word_id: i64, tense_id: i64, update: &NihongoWordTenseUpdate) -> Result<bool>;
+
+    /// Deletes the tense and resets `is_processed` on its word.
+    /// Returns `false` when the tense doesn't exist or doesn't belong to the word.
+    async fn delete_word_tense(&self, word_id: i64, tense_id: i64) -> Result<bool>;
+
+    /// Adds an example sentence and resets `is_processed` so the worker adds it to the anki note.
+    /// Returns `None` when the word doesn't exist.
+    async fn add_word_sentence(&self, word_id: i64, sentence: &NihongoWordSentenceInsert) -> Result<Option<NihongoWordSentence>>;
+
+    /// Oldest first, not including the word's own `sentence`
+    async fn get_word_sentences(&self, word_id: i64) -> Result<Vec<NihongoWordSentence>>;
+
+    async fn update_sentence_audio_status(&self, id: i64, audio_status: &str) -> Result<()>;
+}
+
+/// Whether the error is a unique constraint violation, e.g. renaming a word to one that's already saved
+pub fn is_unique_violation(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .is_some_and(|e| e.is_unique_violation())
+}
+
+/// The parts of a saved word that merging in another version of it can change
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct MergeTarget {
+    /// The word's own sentence and every one in `nihongo_word_sentence`
+    pub sentences: Vec<String>,
+    pub tense_types: Vec<String>
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct MergeChanges {
+    pub sentence: Option<NihongoWordSentenceInsert>,
+    pub tenses: Vec<NihongoWordTenseInsert>
+}
+
+impl MergeChanges {
+    pub fn is_empty(&self) -> bool {
+        self.sentence.is_none() && self.tenses.is_empty()
+    }
+}
+
+/// Same for both backends so merging behaves identically on postgres and sqlite
+pub(crate) fn merge_changes(saved: &MergeTarget, word: &NihongoWordInsert) -> MergeChanges {
+    let new_sentence = word.sentence.trim();
+    let sentence = match new_sentence.is_empty() || saved.sentences.iter().any(|s| s.trim() == new_sentence) {
+        true => None,
+        false => Some(NihongoWordSentenceInsert {
+            sentence: new_sentence.to_string(),
+            sentence_translation: word.sentence_translation.trim().to_string(),
+            source: word.source.label()
+        })
+    };
+
+    let normalize = |t: &str| t.trim().to_lowercase();
+    let mut seen = saved.tense_types.iter().map(|t| normalize(t)).collect::<HashSet<String>>();
+    let tenses = word.word_tenses.iter()
+        .filter(|t| seen.insert(normalize(&t.tense_type)))
+        .cloned()
+        .collect();
+
+    MergeChanges { sentence, tenses }
+}
+
+/// Picks the backend from the url scheme: `postgres://` / `postgresql://` or `sqlite:`
+pub async fn connect(db_url: &str, max_connections: u32) -> Result<Arc<dyn WordStore>> {
+    let store: Arc<dyn WordStore> = match Backend::from_url(db_url)? {
+        Backend::Postgres => Arc::new(PostgresDatabase::connect(db_url, max_connections).await?),
+        Backend::Sqlite => Arc::new(SqliteDatabase::connect(db_url, max_connections).await?)
+    };
+
+    Ok(store)
+}
+
+/// Reads `DATABASE_URL` and optionally `DATABASE_MAX_CONNECTIONS`
+pub async fn from_env() -> Result<Arc<dyn WordStore>> {
+    dotenv().ok();
+    let db_url = std::env::var("DATABASE_URL")?;
+    let max_connections = match std::env::var("DATABASE_MAX_CONNECTIONS") {
+        Ok(v) => v.parse()?,
+        Err(_) => DEFAULT_MAX_CONNECTIONS
+    };
+
+    connect(&db_url, max_connections).await
+}
+
+/// Migrations run at startup when `DATABASE_RUN_MIGRATIONS` is `true` or `1`
+pub fn run_migrations_on_startup() -> bool {
+    dotenv().ok();
+    matches!(std::env::var("DATABASE_RUN_MIGRATIONS").as_deref(), Ok("true") | Ok("1"))
+}
+
+#[derive(Debug, PartialEq)]
+enum Backend {
+    Postgres,
+    Sqlite
+}
+
+impl Backend {
+    fn from_url(db_url: &str) -> Result<Self> {
+        if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+            Ok(Backend::Postgres)
+        } else if db_url.starts_with("sqlite:") {
+            Ok(Backend::Sqlite)
+        } else {
+            bail!("Unsupported DATABASE_URL scheme, expected postgres:// or sqlite:")
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::db::NihongoWordSource;
+
+    #[test]
+    fn picks_backend_from_scheme() {
+        assert_eq!(Backend::from_url("postgresql://u:p@localhost:5432/db").unwrap(), Backend::Postgres);
+        assert_eq!(Backend::from_url("postgres://localhost/db").unwrap(), Backend::Postgres);
+        assert_eq!(Backend::from_url("sqlite://langcrack.db").unwrap(), Backend::Sqlite);
+        assert_eq!(Backend::from_url("sqlite::memory:").unwrap(), Backend::Sqlite);
+        assert!(Backend::from_url("mysql://localhost/db").is_err());
+    }
+
+    #[test]
+    fn merge_adds_new_sentence_and_tense_types() {
+        let saved = MergeTarget {
+            sentences: vec!["毎朝パンを食べる。".to_string()],
+            tense_types: vec!["past".to_string()]
+        };
+        let tense = |w: &str, t: &str| NihongoWordTenseInsert { word: w.to_string(), sentence: format!("{}。", w), tense_type: t.to_string() };
+        let word = NihongoWordInsert {
+            sentence: " 寿司を食べる。".to_string(),
+            sentence_translation: "I eat sushi.".to_string(),
+            word_tenses: vec![tense("食べた", "Past "), tense("食べない", "negative"), tense("食べません", "negative")],
+            source: NihongoWordSource { title: Some("よつばと！".to_string()), location: Some("ch. 3".to_string()), ..Default::default() },
+            ..Default::default()
+        };
+
+        let changes = merge_changes(&saved, &word);
+        assert_eq!(changes.sentence, Some(NihongoWordSentenceInsert {
+            sentence: "寿司を食べる。".to_string(),
+            sentence_translation: "I eat sushi.".to_string(),
+            source: Some("よつばと！, ch. 3".to_string())
+        }));
+        assert_eq!(changes.tenses, vec![tense("食べない", "negative")]);
+    }
+
+    #[test]
+    fn merge_of_same_word_changes_nothing() {
+        let saved = MergeTarget {
+            sentences: vec!["毎朝パンを食べる。".to_string(), "寿司を食べる。".to_string()],
+            tense_types: vec![]
+        };
+        let word = NihongoWordInsert { sentence: "寿司を食べる。".to_string(), ..Default::default() };
+
+        assert!(merge_changes(&saved, &word).is_empty());
+    }
+}
@@ -0,0 +1,371 @@
+use std::fmt;
+
+use crate::models::db::{
+    NihongoWordInsert, NihongoWordTenseInsert, NihongoWordSentenceInsert, NihongoWordUpdate, NihongoWordTenseUpdate,
+    NihongoWordWithTensesStructured, NihongoWordWithTensesStructuredTenses
+};
+
+/// Tense types the prompts ask for, compared after `normalize_tense_type`
+pub const KNOWN_TENSE_TYPES: &[&str] = &[
+    "present",
+    "past",
+    "future",
+    "negative",
+    "past negative",
+    "polite",
+    "polite past",
+    "polite negative",
+    "polite past negative",
+    "te form",
+    "progressive",
+    "volitional",
+    "potential",
+    "passive",
+    "causative",
+    "imperative",
+    "conditional"
+];
+
+/// Every problem found in a word that couldn't be corrected
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub problems: Vec<String>
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.problems.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Checks a word before it's saved, whether it was built by the GPT (`NihongoWordReqWordChatgpt`) or
+/// generated by the LLM (`NihongoWordOpenAiRes`), both are converted into `NihongoWordInsert` first.
+///
+/// Corrects what can be derived: surrounding whitespace, `is_kanji`, a missing reading of a kana only word,
+/// empty mnemonics and the spelling of tense types. Returns what was corrected plus anything that looks off
+/// but is saved anyway (unknown tense types), or every problem that can't be corrected.
+pub fn validate_word(word: &mut NihongoWordInsert) -> Result<Vec<String>, ValidationError> {
+    let mut notes = vec![];
+    let mut problems = vec![];
+
+    for (name, v) in [
+        ("word", &mut word.word),
+        ("word_reading", &mut word.word_reading),
+        ("definition", &mut word.definition),
+        ("sentence", &mut word.sentence),
+        ("sentence_translation", &mut word.sentence_translation)
+    ] {
+        trim(v);
+        if v.is_empty() && name != "word_reading" {
+            problems.push(format!("{} must not be empty", name));
+        }
+    }
+
+    for m in [&mut word.kanji_mnemonic, &mut word.spoken_mnemonic] {
+        if let Some(v) = m {
+            trim(v);
+        }
+        if m.as_deref() == Some("") {
+            *m = None;
+        }
+    }
+
+    let is_kanji = contains_kanji(&word.word);
+    if word.is_kanji != is_kanji {
+        notes.push(format!("is_kanji corrected to {}", is_kanji));
+        word.is_kanji = is_kanji;
+    }
+
+    if word.word_reading.is_empty() && !word.word.is_empty() && is_kana(&word.word) {
+        word.word_reading = word.word.clone();
+        notes.push("word_reading was empty, set to the word".to_string());
+    }
+    if word.word_reading.is_empty() {
+        problems.push("word_reading must not be empty".to_string());
+    } else if !is_kana(&word.word_reading) {
+        problems.push(format!("word_reading must be hiragana or katakana, got {}", word.word_reading));
+    }
+
+    for (i, t) in word.word_tenses.iter_mut().enumerate() {
+        if !check_tense(t, &format!("word_tenses[{}].", i), &mut problems) {
+            notes.push(format!("Unknown tense_type: {}", t.tense_type));
+        }
+    }
+
+    match problems.is_empty() {
+        true => Ok(notes),
+        false => Err(ValidationError { problems })
+    }
+}
+
+/// Applies `update` to the saved word and runs the result through `validate_word`. On success `update` holds
+/// every corrected field, e.g. `is_kanji` recomputed after a rename, so the whole word is written back.
+pub fn validate_word_update(saved: &NihongoWordWithTensesStructured, update: &mut NihongoWordUpdate) -> Result<Vec<String>, ValidationError> {
+    let pick = |new: &Option<String>, saved: &String| new.clone().unwrap_or_else(|| saved.clone());
+    let mut word = NihongoWordInsert {
+        word: pick(&update.word, &saved.word),
+        is_kanji: update.is_kanji.unwrap_or(saved.is_kanji),
+        word_reading: pick(&update.word_reading, &saved.word_reading),
+        definition: pick(&update.definition, &saved.definition),
+        sentence: pick(&update.sentence, &saved.sentence),
+        sentence_translation: pick(&update.sentence_translation, &saved.sentence_translation),
+        kanji_mnemonic: update.kanji_mnemonic.clone().unwrap_or_else(|| saved.kanji_mnemonic.clone()),
+        spoken_mnemonic: update.spoken_mnemonic.clone().unwrap_or_else(|| saved.spoken_mnemonic.clone()),
+        ..Default::default()
+    };
+
+    let notes = validate_word(&mut word)?;
+
+    *update = NihongoWordUpdate {
+        word: Some(word.word),
+        is_kanji: Some(word.is_kanji),
+        word_reading: Some(word.word_reading),
+        definition: Some(word.definition),
+        sentence: Some(word.sentence),
+        sentence_translation: Some(word.sentence_translation),
+        kanji_mnemonic: Some(word.kanji_mnemonic),
+        spoken_mnemonic: Some(word.spoken_mnemonic)
+    };
+
+    Ok(notes)
+}
+
+/// Same as `validate_word_update` for a single tense. An unknown tense type is a problem here rather than a note,
+/// one typed into an edit is a typo and not a kind of tense the LLM came up with.
+pub fn validate_tense_update(saved: &NihongoWordWithTensesStructuredTenses, update: &mut NihongoWordTenseUpdate) -> Result<(), ValidationError> {
+    let mut tense = NihongoWordTenseInsert {
+        word: update.word.clone().unwrap_or_else(|| saved.word.clone()),
+        sentence: update.sentence.clone().unwrap_or_else(|| saved.sentence.clone()),
+        tense_type: update.tense_type.clone().unwrap_or_else(|| saved.tense_type.clone())
+    };
+
+    let mut problems = vec![];
+    if !check_tense(&mut tense, "", &mut problems) {
+        problems.push(format!("Unknown tense_type: {}, expected one of: {}", tense.tense_type, KNOWN_TENSE_TYPES.join(", ")));
+    }
+    if !problems.is_empty() {
+        return Err(ValidationError { problems });
+    }
+
+    *update = NihongoWordTenseUpdate {
+        word: Some(tense.word),
+        sentence: Some(tense.sentence),
+        tense_type: Some(tense.tense_type)
+    };
+
+    Ok(())
+}
+
+/// Checks an example sentence added to a saved word, trimmed and neither part empty like the word's own sentence
+pub fn validate_sentence(sentence: &mut NihongoWordSentenceInsert) -> Result<(), ValidationError> {
+    let mut problems = vec![];
+
+    for (name, v) in [("sentence", &mut sentence.sentence), ("sentence_translation", &mut sentence.sentence_translation)] {
+        trim(v);
+        if v.is_empty() {
+            problems.push(format!("{} must not be empty", name));
+        }
+    }
+    if let Some(source) = &mut sentence.source {
+        trim(source);
+    }
+    if sentence.source.as_deref() == Some("") {
+        sentence.source = None;
+    }
+
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(ValidationError { problems })
+    }
+}
+
+/// Trims the tense and normalizes its type, `prefix` goes before the field name in the problems.
+/// Returns whether the tense type is known, an empty one is only reported as empty.
+fn check_tense(t: &mut NihongoWordTenseInsert, prefix: &str, problems: &mut Vec<String>) -> bool {
+    for (name, v) in [("word", &mut t.word), ("sentence", &mut t.sentence), ("tense_type", &mut t.tense_type)] {
+        trim(v);
+        if v.is_empty() {
+            problems.push(format!("{}{} must not be empty", prefix, name));
+        }
+    }
+
+    if t.tense_type.is_empty() {
+        return true;
+    }
+
+    t.tense_type = normalize_tense_type(&t.tense_type);
+    KNOWN_TENSE_TYPES.contains(&t.tense_type.as_str())
+}
+
+/// Lowercase with single spaces between words, `Past_Negative` and `te-form` become `past negative` and `te form`
+pub fn normalize_tense_type(v: &str) -> String {
+    v.to_lowercase()
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '-')
+        .filter(|p| !p.is_empty())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Whether the text contains a CJK ideograph (or the `々` repeat mark)
+pub fn contains_kanji(v: &str) -> bool {
+    v.chars().any(|c| matches!(c,
+        '\u{3005}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{2A6DF}'
+    ))
+}
+
+/// Hiragana, katakana and the long vowel mark only
+pub fn is_kana(v: &str) -> bool {
+    v.chars().all(|c| matches!(c, '\u{3041}'..='\u{309F}' | '\u{30A0}'..='\u{30FF}'))
+}
+
+fn trim(v: &mut String) {
+    if v.trim().len() != v.len() {
+        *v = v.trim().to_string();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word() -> NihongoWordInsert {
+        NihongoWordInsert {
+            word: "食べる".to_string(),
+            is_kanji: true,
+            word_reading: "たべる".to_string(),
+            definition: "to eat".to_string(),
+            sentence: "毎朝パンを食べる。".to_string(),
+            sentence_translation: "I eat bread every morning.".to_string(),
+            kanji_mnemonic: None,
+            spoken_mnemonic: None,
+            word_tenses: vec![NihongoWordTenseInsert { word: "食べた".to_string(), sentence: "食べた。".to_string(), tense_type: "past".to_string() }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn valid_word_is_unchanged() {
+        let mut w = word();
+        assert_eq!(validate_word(&mut w), Ok(vec![]));
+        assert_eq!(w, word());
+    }
+
+    #[test]
+    fn corrects_obvious_mistakes() {
+        let mut w = word();
+        w.is_kanji = false;
+        w.definition = " to eat ".to_string();
+        w.kanji_mnemonic = Some(" ".to_string());
+        w.word_tenses[0].tense_type = "Te-Form".to_string();
+        w.word_tenses.push(NihongoWordTenseInsert { word: "食べれば".to_string(), sentence: "食べれば。".to_string(), tense_type: "provisional".to_string() });
+
+        let notes = validate_word(&mut w).unwrap();
+        assert_eq!(notes, vec!["is_kanji corrected to true", "Unknown tense_type: provisional"]);
+        assert!(w.is_kanji);
+        assert_eq!(w.definition, "to eat");
+        assert_eq!(w.kanji_mnemonic, None);
+        assert_eq!(w.word_tenses[0].tense_type, "te form");
+
+        let mut w = NihongoWordInsert { word: "すごい".to_string(), is_kanji: true, word_reading: "".to_string(), ..word() };
+        assert_eq!(validate_word(&mut w).unwrap(), vec!["is_kanji corrected to false", "word_reading was empty, set to the word"]);
+        assert_eq!(w.word_reading, "すごい");
+    }
+
+    #[test]
+    fn rejects_what_cant_be_corrected() {
+        let mut w = word();
+        w.word_reading = "taberu".to_string();
+        w.sentence = "  ".to_string();
+        w.word_tenses[0].word = "".to_string();
+
+        let err = validate_word(&mut w).unwrap_err();
+        assert_eq!(err.problems, vec![
+            "sentence must not be empty",
+            "word_reading must be hiragana or katakana, got taberu",
+            "word_tenses[0].word must not be empty"
+        ]);
+
+        let mut w = NihongoWordInsert { word_reading: "".to_string(), ..word() };
+        assert_eq!(validate_word(&mut w).unwrap_err().problems, vec!["word_reading must not be empty"]);
+    }
+
+    #[test]
+    fn validates_updates_against_the_saved_word() {
+        let w = word();
+        let saved = NihongoWordWithTensesStructured {
+            id: 1,
+            word: w.word,
+            is_kanji: w.is_kanji,
+            word_reading: w.word_reading,
+            definition: w.definition,
+            sentence: w.sentence,
+            sentence_translation: w.sentence_translation,
+            kanji_mnemonic: Some("食 is a roof over food".to_string()),
+            spoken_mnemonic: None,
+            is_processed: true,
+            word_tenses: vec![],
+            sentences: vec![],
+            created_by: None,
+            source: None,
+            created_at: Default::default()
+        };
+
+        let mut update = NihongoWordUpdate { word: Some(" たべる ".to_string()), kanji_mnemonic: Some(None), ..Default::default() };
+        assert_eq!(validate_word_update(&saved, &mut update).unwrap(), vec!["is_kanji corrected to false"]);
+        assert_eq!(update.word.as_deref(), Some("たべる"));
+        assert_eq!(update.is_kanji, Some(false));
+        assert_eq!(update.definition.as_deref(), Some("to eat"));
+        assert_eq!(update.kanji_mnemonic, Some(None));
+
+        let mut update = NihongoWordUpdate { word_reading: Some("taberu".to_string()), definition: Some(" ".to_string()), ..Default::default() };
+        assert_eq!(validate_word_update(&saved, &mut update).unwrap_err().problems, vec![
+            "definition must not be empty",
+            "word_reading must be hiragana or katakana, got taberu"
+        ]);
+
+        let tense = NihongoWordWithTensesStructuredTenses {
+            id: 1,
+            word_id: 1,
+            word: "食べた".to_string(),
+            sentence: "食べた。".to_string(),
+            tense_type: "past".to_string(),
+            created_at: Default::default()
+        };
+        let mut update = NihongoWordTenseUpdate { tense_type: Some("Past_Negative".to_string()), ..Default::default() };
+        validate_tense_update(&tense, &mut update).unwrap();
+        assert_eq!(update.tense_type.as_deref(), Some("past negative"));
+        assert_eq!(update.word.as_deref(), Some("食べた"));
+
+        let mut update = NihongoWordTenseUpdate { tense_type: Some("provisional".to_string()), sentence: Some("".to_string()), ..Default::default() };
+        let problems = validate_tense_update(&tense, &mut update).unwrap_err().problems;
+        assert_eq!(problems[0], "sentence must not be empty");
+        assert!(problems[1].starts_with("Unknown tense_type: provisional, expected one of: present, past"));
+    }
+
+    #[test]
+    fn validates_sentences() {
+        let mut s = NihongoWordSentenceInsert {
+            sentence: " 寿司を食べる。 ".to_string(),
+            sentence_translation: "I eat sushi. ".to_string(),
+            source: Some(" ".to_string())
+        };
+        validate_sentence(&mut s).unwrap();
+        assert_eq!(s, NihongoWordSentenceInsert { sentence: "寿司を食べる。".to_string(), sentence_translation: "I eat sushi.".to_string(), source: None });
+
+        s.sentence_translation = " ".to_string();
+        assert_eq!(validate_sentence(&mut s).unwrap_err().problems, vec!["sentence_translation must not be empty"]);
+    }
+
+    #[test]
+    fn detects_kanji_and_kana() {
+        assert!(contains_kanji("人々"));
+        assert!(!contains_kanji("コーヒー"));
+        assert!(is_kana("コーヒー"));
+        assert!(is_kana("たべる"));
+        assert!(!is_kana("食べる"));
+        assert!(!is_kana("たべ る"));
+    }
+}
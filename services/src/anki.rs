@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashMap};
+use anyhow::{Result, bail};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+
+pub mod mock;
+
+const ANKI_CONNECT_URL: &str = "http://localhost:8765";
+const ANKI_CONNECT_VERSION: u32 = 6;
+
+/// AnkiConnect (https://foosoft.net/projects/anki-connect) client, every action is sent with version 6
+#[derive(Clone, Debug)]
+pub struct AnkiClient {
+    client: reqwest::Client,
+    url: String,
+    key: Option<String>
+}
+
+impl AnkiClient {
+    /// `url` defaults to `http://localhost:8765`, the key is only needed when `apiKey` is set in the AnkiConnect config
+    pub fn new(url: Option<&str>, key: Option<&str>) -> Self {
+        AnkiClient {
+            client: reqwest::Client::new(),
+            url: url.unwrap_or(ANKI_CONNECT_URL).to_string(),
+            key: key.map(|k| k.to_string())
+        }
+    }
+
+    /// Sends an AnkiConnect action and deserializes its `result`
+    pub async fn invoke<P, R>(&self, action: &str, params: P) -> Result<R>
+    where
+        P: Serialize,
+        R: DeserializeOwned
+    {
+        let request = AnkiRequest {
+            action,
+            version: ANKI_CONNECT_VERSION,
+            key: self.key.as_deref(),
+            params
+        };
+
+        let res: AnkiResponse = self.client
+            .post(&self.url)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(e) = res.error {
+            bail!("Response from anki contained error(s) | action: {} | error(s): {}", action, e);
+        }
+
+        Ok(serde_json::from_value(res.result)?)
+    }
+
+    pub async fn version(&self) -> Result<u32> {
+        self.invoke("version", serde_json::json!({})).await
+    }
+
+    /// Fails when anki isn't running or its AnkiConnect is older than the version this client speaks
+    pub async fn check_version(&self) -> Result<()> {
+        let version = self.version().await?;
+        if version < ANKI_CONNECT_VERSION {
+            bail!("AnkiConnect version {} is too old, at least {} is required", version, ANKI_CONNECT_VERSION);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the id of the new note
+    pub async fn add_note(&self, note: &NewNote) -> Result<i64> {
+        self.invoke("addNote", serde_json::json!({ "note": note })).await
+    }
+
+    /// Only the given fields are changed
+    pub async fn update_note_fields(&self, note_id: i64, fields: &BTreeMap<String, String>) -> Result<()> {
+        self.invoke("updateNoteFields", serde_json::json!({
+            "note": {
+                "id": note_id,
+                "fields": fields
+            }
+        })).await
+    }
+
+    pub async fn add_tags(&self, note_ids: &[i64], tags: &[String]) -> Result<()> {
+        self.invoke("addTags", serde_json::json!({
+            "notes": note_ids,
+            "tags": tags.join(" ")
+        })).await
+    }
+
+    /// Ids of the notes matching an anki search `query`
+    pub async fn find_notes(&self, query: &str) -> Result<Vec<i64>> {
+        self.invoke("findNotes", serde_json::json!({ "query": query })).await
+    }
+
+    pub async fn notes_info(&self, note_ids: &[i64]) -> Result<Vec<NoteInfo>> {
+        self.invoke("notesInfo", serde_json::json!({ "notes": note_ids })).await
+    }
+
+    /// Returns the file name anki saved the file as
+    pub async fn store_media_file(&self, file: &MediaFile) -> Result<String> {
+        self.invoke("storeMediaFile", file).await
+    }
+
+    /// Returns the id of the deck, an existing deck is left as is
+    pub async fn create_deck(&self, name: &str) -> Result<i64> {
+        self.invoke("createDeck", serde_json::json!({ "deck": name })).await
+    }
+
+    pub async fn deck_names(&self) -> Result<Vec<String>> {
+        self.invoke("deckNames", serde_json::json!({})).await
+    }
+
+    pub async fn model_names(&self) -> Result<Vec<String>> {
+        self.invoke("modelNames", serde_json::json!({})).await
+    }
+
+    /// Creates a note type, fails when one with the same name exists
+    pub async fn create_model(&self, model: &NewModel) -> Result<()> {
+        let _: Value = self.invoke("createModel", model).await?;
+        Ok(())
+    }
+
+    pub async fn sync(&self) -> Result<()> {
+        self.invoke("sync", serde_json::json!({})).await
+    }
+}
+
+
+#[derive(Debug, Serialize)]
+struct AnkiRequest<'a, P> {
+    action: &'a str,
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<&'a str>,
+    params: P
+}
+
+#[derive(Debug, Deserialize)]
+struct AnkiResponse {
+    #[serde(default)]
+    result: Value,
+    error: Option<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewNote {
+    pub deck_name: String,
+    pub model_name: String,
+    pub fields: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<NoteOptions>,
+    pub tags: Vec<String>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteOptions {
+    pub allow_duplicate: bool,
+    /// `deck` or `collection`
+    pub duplicate_scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duplicate_scope_options: Option<DuplicateScopeOptions>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateScopeOptions {
+    pub deck_name: String,
+    pub check_children: bool,
+    pub check_all_models: bool
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteInfo {
+    pub note_id: i64,
+    pub model_name: String,
+    pub tags: Vec<String>,
+    pub fields: HashMap<String, NoteField>,
+    #[serde(default)]
+    pub cards: Vec<i64>
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct NoteField {
+    pub value: String,
+    pub order: u32
+}
+
+/// A note type, the first of `in_order_fields` is the sort field anki checks duplicates on
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewModel {
+    pub model_name: String,
+    pub in_order_fields: Vec<String>,
+    pub css: String,
+    pub is_cloze: bool,
+    pub card_templates: Vec<CardTemplate>
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct CardTemplate {
+    pub name: String,
+    pub front: String,
+    pub back: String
+}
+
+/// The content is one of `data` (base64), `path` (on the machine running anki) or `url`
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaFile {
+    pub filename: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delete_existing: Option<bool>
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::mock::serve_once;
+
+    #[tokio::test]
+    async fn sends_typed_actions() {
+        let (url, req) = serve_once(serde_json::json!({ "result": 1496198395707i64, "error": null })).await;
+        let client = AnkiClient::new(Some(&url), Some("secret"));
+
+        let note = NewNote {
+            deck_name: "Default".to_string(),
+            model_name: "Basic".to_string(),
+            fields: BTreeMap::from([("Front".to_string(), "食べる".to_string())]),
+            options: Some(NoteOptions { allow_duplicate: false, duplicate_scope: "deck".to_string(), duplicate_scope_options: None }),
+            tags: vec!["lang-crack".to_string()]
+        };
+        assert_eq!(client.add_note(&note).await.unwrap(), 1496198395707);
+
+        assert_eq!(req.await.unwrap(), serde_json::json!({
+            "action": "addNote",
+            "version": 6,
+            "key": "secret",
+            "params": {
+                "note": {
+                    "deckName": "Default",
+                    "modelName": "Basic",
+                    "fields": { "Front": "食べる" },
+                    "options": { "allowDuplicate": false, "duplicateScope": "deck" },
+                    "tags": ["lang-crack"]
+                }
+            }
+        }));
+    }
+
+    #[tokio::test]
+    async fn creates_models() {
+        let (url, req) = serve_once(serde_json::json!({ "result": { "id": 1551462107104i64 }, "error": null })).await;
+
+        let model = NewModel {
+            model_name: "Words".to_string(),
+            in_order_fields: vec!["Word".to_string(), "Definition".to_string()],
+            css: ".card {}".to_string(),
+            is_cloze: false,
+            card_templates: vec![CardTemplate { name: "Recognition".to_string(), front: "{{Word}}".to_string(), back: "{{Definition}}".to_string() }]
+        };
+        AnkiClient::new(Some(&url), None).create_model(&model).await.unwrap();
+
+        assert_eq!(req.await.unwrap()["params"], serde_json::json!({
+            "modelName": "Words",
+            "inOrderFields": ["Word", "Definition"],
+            "css": ".card {}",
+            "isCloze": false,
+            "cardTemplates": [{ "Name": "Recognition", "Front": "{{Word}}", "Back": "{{Definition}}" }]
+        }));
+    }
+
+    #[tokio::test]
+    async fn parses_results_and_errors() {
+        let (url, req) = serve_once(serde_json::json!({
+            "result": [{
+                "noteId": 1502298033753i64,
+                "modelName": "Basic",
+                "tags": ["lang-crack"],
+                "fields": { "Front": { "value": "食べる", "order": 0 } },
+                "cards": [1498938915662i64]
+            }],
+            "error": null
+        })).await;
+        let notes = AnkiClient::new(Some(&url), None).notes_info(&[1502298033753]).await.unwrap();
+        assert_eq!(notes[0].fields["Front"].value, "食べる");
+        assert!(req.await.unwrap().get("key").is_none());
+
+        let (url, _) = serve_once(serde_json::json!({ "result": null, "error": null })).await;
+        AnkiClient::new(Some(&url), None).sync().await.unwrap();
+
+        let (url, _) = serve_once(serde_json::json!({ "result": null, "error": "deck was not found: Missing" })).await;
+        let err = AnkiClient::new(Some(&url), None).find_notes("deck:Missing").await.unwrap_err();
+        assert_eq!(err.to_string(), "Response from anki contained error(s) | action: findNotes | error(s): deck was not found: Missing");
+    }
+}
@@ -0,0 +1,39 @@
+use serde_json::Value;
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpListener, task::JoinHandle};
+
+/// Offline AnkiConnect for tests, answers a single http request with `response`.
+/// Returns the url to point `AnkiClient` at and a handle to the json body that was posted.
+pub async fn serve_once(response: Value) -> (String, JoinHandle<Value>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("failed to bind mock AnkiConnect");
+    let url = format!("http://{}", listener.local_addr().expect("mock AnkiConnect has no address"));
+
+    let handle = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.expect("mock AnkiConnect failed to accept");
+
+        let mut req = vec![];
+        let mut buf = [0u8; 4096];
+        let body = loop {
+            let n = stream.read(&mut buf).await.expect("mock AnkiConnect failed to read");
+            assert!(n > 0, "connection closed before the whole request was read: {}", String::from_utf8_lossy(&req));
+            req.extend_from_slice(&buf[..n]);
+
+            let text = String::from_utf8_lossy(&req).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let len = head.lines()
+                    .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                if body.len() >= len {
+                    break body.to_string();
+                }
+            }
+        };
+
+        let res = response.to_string();
+        let http = format!("HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}", res.len(), res);
+        stream.write_all(http.as_bytes()).await.expect("mock AnkiConnect failed to respond");
+
+        serde_json::from_str(&body).expect("AnkiConnect request body is not json")
+    });
+
+    (url, handle)
+}
@@ -1 +1,3 @@
 pub mod oai;
+pub mod llm;
+pub mod anki;
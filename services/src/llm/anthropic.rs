@@ -0,0 +1,156 @@
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use super::{LlmClient, ChatMessage, ChatRole, ResponseSchema};
+
+const ANTHROPIC_API_BASE: &str = "https://api.anthropic.com";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const MAX_TOKENS: u32 = 4096;
+
+#[derive(Clone, Debug)]
+pub struct AnthropicClient {
+    client: reqwest::Client,
+    api_key: String,
+    model: String,
+    base_url: String
+}
+
+impl AnthropicClient {
+    pub fn new(api_key: String, model: &str, base_url: Option<&str>) -> Self {
+        AnthropicClient {
+            client: reqwest::Client::new(),
+            api_key,
+            model: model.to_string(),
+            base_url: base_url.unwrap_or(ANTHROPIC_API_BASE).trim_end_matches('/').to_string()
+        }
+    }
+
+    /// With a `schema` claude is forced to call a tool taking it as input, the input is returned as JSON
+    async fn create_message(&self, messages: &[ChatMessage], schema: Option<&ResponseSchema>, temperature: Option<f32>) -> Result<String> {
+        // anthropic takes the system prompt as a top level field instead of a message
+        let system = messages.iter()
+            .filter(|m| m.role == ChatRole::System)
+            .map(|m| m.content.as_str())
+            .collect::<Vec<&str>>()
+            .join("\n\n");
+
+        let req_messages = messages.iter()
+            .filter(|m| m.role != ChatRole::System)
+            .map(|m| AnthropicMessage {
+                role: match m.role {
+                    ChatRole::Assistant => "assistant",
+                    _ => "user"
+                },
+                content: m.content.clone()
+            })
+            .collect::<Vec<AnthropicMessage>>();
+
+        let request = AnthropicRequest {
+            model: &self.model,
+            max_tokens: MAX_TOKENS,
+            system: (!system.is_empty()).then_some(system),
+            messages: req_messages,
+            temperature,
+            tools: schema.map(|s| vec![AnthropicTool {
+                name: &s.name,
+                description: "Respond by calling this tool with the result",
+                input_schema: &s.schema
+            }]),
+            tool_choice: schema.map(|s| AnthropicToolChoice { r#type: "tool", name: &s.name })
+        };
+
+        let res: AnthropicResponse = self.client
+            .post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(&request)
+            .send()
+            .await?
+            .json()
+            .await?;
+
+        if let Some(e) = res.error {
+            bail!("Response from anthropic contained error | type: {} | message: {}", e.r#type, e.message);
+        }
+
+        if schema.is_some() {
+            if let Some(input) = res.content.iter().find(|c| c.r#type == "tool_use").and_then(|c| c.input.as_ref()) {
+                return Ok(serde_json::to_string(input)?);
+            }
+        }
+
+        res.content
+            .into_iter()
+            .find(|c| c.r#type == "text")
+            .and_then(|c| c.text)
+            .ok_or_else(|| anyhow!("Text content missing from anthropic message"))
+    }
+}
+
+#[async_trait]
+impl LlmClient for AnthropicClient {
+    async fn chat_json(&self, messages: &[ChatMessage], schema: &ResponseSchema) -> Result<String> {
+        self.create_message(messages, Some(schema), None).await
+    }
+
+    async fn chat_raw(&self, messages: &[ChatMessage]) -> Result<String> {
+        self.create_message(messages, None, Some(0.2)).await
+    }
+}
+
+
+#[derive(Debug, Serialize)]
+struct AnthropicRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<AnthropicTool<'a>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<AnthropicToolChoice<'a>>
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicTool<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a Value
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicToolChoice<'a> {
+    r#type: &'static str,
+    name: &'a str
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicMessage {
+    role: &'static str,
+    content: String
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    #[serde(default)]
+    content: Vec<AnthropicContent>,
+    error: Option<AnthropicError>
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicContent {
+    r#type: String,
+    text: Option<String>,
+    input: Option<Value>
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicError {
+    r#type: String,
+    message: String
+}
@@ -0,0 +1,42 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use async_openai::{Client, config::OpenAIConfig};
+
+use super::{LlmClient, ChatMessage, ResponseSchema, extract_json, openai::{create_chat_completion, OutputFormat}};
+
+/// Ollama's OpenAI compatible endpoint, llama.cpp's server listens on `http://localhost:8080/v1`
+const LOCAL_API_BASE: &str = "http://localhost:11434/v1";
+
+#[derive(Clone, Debug)]
+pub struct LocalClient {
+    client: Client<OpenAIConfig>,
+    model: String
+}
+
+impl LocalClient {
+    pub fn new(api_key: Option<&str>, model: &str, base_url: Option<&str>) -> Self {
+        // local servers generally ignore the key but async-openai always sends one
+        let config = OpenAIConfig::new()
+            .with_api_key(api_key.unwrap_or("local"))
+            .with_api_base(base_url.unwrap_or(LOCAL_API_BASE));
+
+        LocalClient {
+            client: Client::with_config(config),
+            model: model.to_string()
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for LocalClient {
+    // tool calling depends on the model (and its chat template), so only JSON mode is asked for and
+    // the object is picked out of whatever came back
+    async fn chat_json(&self, messages: &[ChatMessage], _schema: &ResponseSchema) -> Result<String> {
+        let res = create_chat_completion(&self.client, &self.model, messages, OutputFormat::JsonObject, None).await?;
+        Ok(extract_json(&res).to_string())
+    }
+
+    async fn chat_raw(&self, messages: &[ChatMessage]) -> Result<String> {
+        create_chat_completion(&self.client, &self.model, messages, OutputFormat::Text, Some(0.2)).await
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use async_openai::{Client, config::OpenAIConfig};
+
+use super::{LlmClient, ChatMessage, ResponseSchema, openai::{create_chat_completion, OutputFormat}};
+
+const MISTRAL_API_BASE: &str = "https://api.mistral.ai/v1";
+
+#[derive(Clone, Debug)]
+pub struct MistralClient {
+    client: Client<OpenAIConfig>,
+    model: String
+}
+
+impl MistralClient {
+    pub fn new(api_key: String, model: &str, base_url: Option<&str>) -> Self {
+        let config = OpenAIConfig::new()
+            .with_api_key(api_key)
+            .with_api_base(base_url.unwrap_or(MISTRAL_API_BASE));
+
+        MistralClient {
+            client: Client::with_config(config),
+            model: model.to_string()
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for MistralClient {
+    async fn chat_json(&self, messages: &[ChatMessage], schema: &ResponseSchema) -> Result<String> {
+        create_chat_completion(&self.client, &self.model, messages, OutputFormat::Tool(schema), None).await
+    }
+
+    async fn chat_raw(&self, messages: &[ChatMessage]) -> Result<String> {
+        create_chat_completion(&self.client, &self.model, messages, OutputFormat::Text, Some(0.2)).await
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::{HashMap, VecDeque}, path::Path, sync::Mutex};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use sha2::{Sha256, Digest};
+
+use super::{LlmClient, ChatMessage, ChatRole, ResponseSchema};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockResponse {
+    Ok(String),
+    Err(String)
+}
+
+/// Offline `LlmClient` for tests.
+///
+/// Scripted responses are handed out first, in order, one per call. Once the script is empty
+/// the prompt is hashed with `prompt_hash` and the matching fixture is replayed.
+#[derive(Debug, Default)]
+pub struct MockLlmClient {
+    fixtures: HashMap<String, String>,
+    script: Mutex<VecDeque<MockResponse>>,
+    calls: Mutex<Vec<Vec<ChatMessage>>>
+}
+
+impl MockLlmClient {
+    pub fn new() -> Self {
+        MockLlmClient::default()
+    }
+
+    /// Loads every `<prompt hash>.json` file in `dir` as a fixture
+    pub fn from_fixture_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let mut fixtures = HashMap::new();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+
+            let hash = path.file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| anyhow!("Invalid fixture file name: {}", path.display()))?
+                .to_string();
+
+            fixtures.insert(hash, std::fs::read_to_string(&path)?);
+        }
+
+        Ok(MockLlmClient {
+            fixtures,
+            ..Default::default()
+        })
+    }
+
+    pub fn with_fixture(mut self, messages: &[ChatMessage], response: impl Into<String>) -> Self {
+        self.fixtures.insert(prompt_hash(messages), response.into());
+        self
+    }
+
+    pub fn with_script(self, script: Vec<MockResponse>) -> Self {
+        self.script.lock().expect("mock script lock poisoned").extend(script);
+        self
+    }
+
+    /// Every message list the client was called with, in call order
+    pub fn calls(&self) -> Vec<Vec<ChatMessage>> {
+        self.calls.lock().expect("mock calls lock poisoned").clone()
+    }
+
+    fn respond(&self, messages: &[ChatMessage]) -> Result<String> {
+        self.calls.lock().expect("mock calls lock poisoned").push(messages.to_vec());
+
+        if let Some(scripted) = self.script.lock().expect("mock script lock poisoned").pop_front() {
+            return match scripted {
+                MockResponse::Ok(r) => Ok(r),
+                MockResponse::Err(e) => bail!(e)
+            };
+        }
+
+        let hash = prompt_hash(messages);
+        match self.fixtures.get(&hash) {
+            Some(r) => Ok(r.clone()),
+            None => bail!("No mock fixture recorded for prompt hash: {}", hash)
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for MockLlmClient {
+    async fn chat_json(&self, messages: &[ChatMessage], _schema: &ResponseSchema) -> Result<String> {
+        self.respond(messages)
+    }
+
+    async fn chat_raw(&self, messages: &[ChatMessage]) -> Result<String> {
+        self.respond(messages)
+    }
+}
+
+/// Stable (across builds and platforms) sha256 of the roles and contents of a prompt
+pub fn prompt_hash(messages: &[ChatMessage]) -> String {
+    let mut hasher = Sha256::new();
+
+    for m in messages {
+        let role = match m.role {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant"
+        };
+        hasher.update(role.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(m.content.as_bytes());
+        hasher.update([0u8]);
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt() -> Vec<ChatMessage> {
+        vec![ChatMessage::system("system"), ChatMessage::user("Word: 食べる")]
+    }
+
+    fn schema() -> ResponseSchema {
+        ResponseSchema { name: "Test".to_string(), schema: serde_json::json!({ "type": "object" }) }
+    }
+
+    #[tokio::test]
+    async fn replays_fixture_by_prompt_hash() {
+        let client = MockLlmClient::new().with_fixture(&prompt(), "{\"words\": []}");
+
+        assert_eq!(client.chat_json(&prompt(), &schema()).await.unwrap(), "{\"words\": []}");
+        assert!(client.chat_json(&[ChatMessage::user("other")], &schema()).await.is_err());
+        assert_eq!(client.calls().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn script_is_consumed_before_fixtures() {
+        let client = MockLlmClient::new()
+            .with_fixture(&prompt(), "fixture")
+            .with_script(vec![MockResponse::Err("rate limited".to_string()), MockResponse::Ok("scripted".to_string())]);
+
+        assert_eq!(client.chat_raw(&prompt()).await.unwrap_err().to_string(), "rate limited");
+        assert_eq!(client.chat_raw(&prompt()).await.unwrap(), "scripted");
+        assert_eq!(client.chat_raw(&prompt()).await.unwrap(), "fixture");
+    }
+
+    #[tokio::test]
+    async fn loads_fixture_dir() {
+        let dir = std::env::temp_dir().join(format!("mock_llm_fixtures_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("{}.json", prompt_hash(&prompt()))), "{}").unwrap();
+        std::fs::write(dir.join("README.md"), "ignored").unwrap();
+
+        let client = MockLlmClient::from_fixture_dir(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(client.chat_json(&prompt(), &schema()).await.unwrap(), "{}");
+    }
+
+    #[test]
+    fn prompt_hash_depends_on_role_and_content() {
+        assert_eq!(prompt_hash(&prompt()), prompt_hash(&prompt()));
+        assert_ne!(prompt_hash(&[ChatMessage::system("a")]), prompt_hash(&[ChatMessage::user("a")]));
+        assert_ne!(prompt_hash(&[ChatMessage::user("ab"), ChatMessage::user("c")]), prompt_hash(&[ChatMessage::user("a"), ChatMessage::user("bc")]));
+    }
+}
@@ -0,0 +1,222 @@
+pub mod openai;
+pub mod mistral;
+pub mod anthropic;
+pub mod local;
+pub mod mock;
+
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use data::models::oai::ModelProvider;
+use dotenv::dotenv;
+use schemars::{JsonSchema, gen::SchemaSettings};
+use serde_json::Value;
+
+use self::{openai::OpenAiClient, mistral::MistralClient, anthropic::AnthropicClient, local::LocalClient};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatRole {
+    System,
+    User,
+    Assistant
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChatMessage {
+    pub role: ChatRole,
+    pub content: String
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        ChatMessage { role: ChatRole::System, content: content.into() }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        ChatMessage { role: ChatRole::User, content: content.into() }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        ChatMessage { role: ChatRole::Assistant, content: content.into() }
+    }
+}
+
+/// JSON schema `chat_json` replies have to match, passed to the provider as a tool / function definition
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResponseSchema {
+    pub name: String,
+    pub schema: Value
+}
+
+impl ResponseSchema {
+    /// Schema of `T` with every subschema inlined, some providers don't resolve `$ref` in tool parameters
+    pub fn for_type<T: JsonSchema>() -> Result<Self> {
+        let root = SchemaSettings::draft07()
+            .with(|s| s.inline_subschemas = true)
+            .into_generator()
+            .into_root_schema_for::<T>();
+
+        let mut schema = serde_json::to_value(root)?;
+        if let Some(o) = schema.as_object_mut() {
+            o.remove("$schema");
+        }
+
+        Ok(ResponseSchema {
+            name: T::schema_name(),
+            schema
+        })
+    }
+}
+
+/// A single chat completion backend. Implementations only talk to their provider,
+/// retries and deserialization are handled by `oai::ChatAsync`.
+#[async_trait]
+pub trait LlmClient: Send + Sync + std::fmt::Debug {
+    /// Completion where the provider is asked to return a JSON object matching `schema`, natively
+    /// (tool / function calling) where it's supported
+    async fn chat_json(&self, messages: &[ChatMessage], schema: &ResponseSchema) -> Result<String>;
+
+    async fn chat_raw(&self, messages: &[ChatMessage]) -> Result<String>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LlmConfig {
+    pub provider: ModelProvider,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub base_url: Option<String>
+}
+
+impl LlmConfig {
+    /// Reads `LLM_PROVIDER`, `LLM_MODEL`, `LLM_API_KEY` and `LLM_BASE_URL`.
+    /// When `LLM_API_KEY` isn't set the provider specific key (`OPENAI_API_KEY`, `MISTRAL_API_KEY`, `ANTHROPIC_API_KEY`) is used.
+    pub fn from_env() -> Result<Self> {
+        dotenv().ok();
+
+        let provider: ModelProvider = match std::env::var("LLM_PROVIDER") {
+            Ok(p) => p.parse()?,
+            Err(_) => ModelProvider::Mistral
+        };
+
+        let model = std::env::var("LLM_MODEL")
+            .unwrap_or_else(|_| default_model(&provider).to_string());
+
+        let api_key = std::env::var("LLM_API_KEY").ok()
+            .or_else(|| provider_key_var(&provider).and_then(|v| std::env::var(v).ok()));
+
+        let base_url = std::env::var("LLM_BASE_URL").ok();
+
+        Ok(LlmConfig {
+            provider,
+            model,
+            api_key,
+            base_url
+        })
+    }
+
+    fn require_key(&self) -> Result<String> {
+        self.api_key.clone().ok_or_else(|| anyhow!(
+            "Missing API key for {:?}, set LLM_API_KEY or {}",
+            self.provider,
+            provider_key_var(&self.provider).unwrap_or("LLM_API_KEY")
+        ))
+    }
+}
+
+pub fn from_config(config: &LlmConfig) -> Result<Arc<dyn LlmClient>> {
+    let client: Arc<dyn LlmClient> = match config.provider {
+        ModelProvider::OpenAi => Arc::new(OpenAiClient::new(config.require_key()?, &config.model, config.base_url.as_deref())),
+        ModelProvider::Mistral => Arc::new(MistralClient::new(config.require_key()?, &config.model, config.base_url.as_deref())),
+        ModelProvider::Anthropic => Arc::new(AnthropicClient::new(config.require_key()?, &config.model, config.base_url.as_deref())),
+        ModelProvider::Local => Arc::new(LocalClient::new(config.api_key.as_deref(), &config.model, config.base_url.as_deref()))
+    };
+
+    Ok(client)
+}
+
+pub fn from_env() -> Result<Arc<dyn LlmClient>> {
+    from_config(&LlmConfig::from_env()?)
+}
+
+fn default_model(provider: &ModelProvider) -> &'static str {
+    match provider {
+        ModelProvider::OpenAi => "gpt-4-turbo",
+        ModelProvider::Mistral => "mistral-medium",
+        ModelProvider::Anthropic => "claude-3-haiku-20240307",
+        ModelProvider::Local => "llama3"
+    }
+}
+
+fn provider_key_var(provider: &ModelProvider) -> Option<&'static str> {
+    match provider {
+        ModelProvider::OpenAi => Some("OPENAI_API_KEY"),
+        ModelProvider::Mistral => Some("MISTRAL_API_KEY"),
+        ModelProvider::Anthropic => Some("ANTHROPIC_API_KEY"),
+        ModelProvider::Local => None
+    }
+}
+
+/// For providers without structured outputs, the first JSON object in the reply. Anything around it,
+/// like code fences or a sentence of prose, is dropped. Escapes are kept as they are.
+pub fn extract_json(output: &str) -> &str {
+    let Some(start) = output.find('{') else {
+        return output.trim();
+    };
+
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in output[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &output[start..=start + i];
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // unterminated, left to fail parsing so the error is sent back to the model
+    output[start..].trim_end()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::models::oai::NihongoWordOpenAiRes;
+
+    #[test]
+    fn extracts_first_object() {
+        assert_eq!(extract_json("{\"a\": 1}"), "{\"a\": 1}");
+        assert_eq!(extract_json("```json\n{\"a\": {\"b\": \"}\"}}\n```"), "{\"a\": {\"b\": \"}\"}}");
+        assert_eq!(extract_json("Here you go: {\"a\": \"say \\\"hi\\\" {\"} and {\"b\": 2}"), "{\"a\": \"say \\\"hi\\\" {\"}");
+        assert_eq!(extract_json("{\"a\": [1, "), "{\"a\": [1,");
+        assert_eq!(extract_json(" no json "), "no json");
+    }
+
+    #[test]
+    fn response_schema_is_self_contained() {
+        let schema = ResponseSchema::for_type::<NihongoWordOpenAiRes>().unwrap();
+
+        assert_eq!(schema.name, "NihongoWordOpenAiRes");
+        assert!(schema.schema.get("$schema").is_none());
+        assert!(schema.schema.get("definitions").is_none());
+        assert_eq!(schema.schema["properties"]["word_tenses"]["items"]["type"], "object");
+    }
+}
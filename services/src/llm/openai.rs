@@ -0,0 +1,135 @@
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+
+use async_openai::{
+    types::{
+        CreateChatCompletionRequestArgs, ChatCompletionResponseFormat, ChatCompletionResponseFormatType, ChatCompletionRequestMessage,
+        ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionRequestAssistantMessageArgs,
+        ChatCompletionTool, ChatCompletionToolType, ChatCompletionToolChoiceOption, ChatCompletionNamedToolChoice, FunctionObject, FunctionName,
+    },
+    Client, config::OpenAIConfig,
+};
+
+use super::{LlmClient, ChatMessage, ChatRole, ResponseSchema};
+
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+#[derive(Clone, Debug)]
+pub struct OpenAiClient {
+    client: Client<OpenAIConfig>,
+    model: String
+}
+
+impl OpenAiClient {
+    pub fn new(api_key: String, model: &str, base_url: Option<&str>) -> Self {
+        let config = OpenAIConfig::new()
+            .with_api_key(api_key)
+            .with_api_base(base_url.unwrap_or(OPENAI_API_BASE));
+
+        OpenAiClient {
+            client: Client::with_config(config),
+            model: model.to_string()
+        }
+    }
+}
+
+#[async_trait]
+impl LlmClient for OpenAiClient {
+    async fn chat_json(&self, messages: &[ChatMessage], schema: &ResponseSchema) -> Result<String> {
+        create_chat_completion(&self.client, &self.model, messages, OutputFormat::Tool(schema), None).await
+    }
+
+    async fn chat_raw(&self, messages: &[ChatMessage]) -> Result<String> {
+        create_chat_completion(&self.client, &self.model, messages, OutputFormat::Text, Some(0.2)).await
+    }
+}
+
+/// How the reply of `create_chat_completion` is constrained
+pub(crate) enum OutputFormat<'a> {
+    Text,
+    /// `response_format: json_object`, any JSON object
+    JsonObject,
+    /// A single function taking `schema` as its parameters that the model is forced to call,
+    /// the arguments of the call are returned
+    Tool(&'a ResponseSchema)
+}
+
+/// Shared by every provider that speaks the OpenAI chat completions API
+pub(crate) async fn create_chat_completion(
+    client: &Client<OpenAIConfig>,
+    model: &str,
+    messages: &[ChatMessage],
+    format: OutputFormat<'_>,
+    temperature: Option<f32>
+) -> Result<String> {
+    let mut request = CreateChatCompletionRequestArgs::default();
+    request
+        .stream(false)
+        .model(model)
+        .messages(to_openai_messages(messages)?);
+
+    match format {
+        OutputFormat::Text => {},
+        OutputFormat::JsonObject => {
+            request.response_format(ChatCompletionResponseFormat {
+                r#type: ChatCompletionResponseFormatType::JsonObject
+            });
+        },
+        OutputFormat::Tool(schema) => {
+            request
+                .tools(vec![ChatCompletionTool {
+                    r#type: ChatCompletionToolType::Function,
+                    function: FunctionObject {
+                        name: schema.name.clone(),
+                        description: Some("Respond by calling this function with the result".to_string()),
+                        parameters: Some(schema.schema.clone())
+                    }
+                }])
+                .tool_choice(ChatCompletionToolChoiceOption::Named(ChatCompletionNamedToolChoice {
+                    r#type: ChatCompletionToolType::Function,
+                    function: FunctionName { name: schema.name.clone() }
+                }));
+        }
+    }
+
+    if let Some(t) = temperature {
+        request.temperature(t);
+    }
+
+    let message = client.chat().create(request.build()?).await?
+        .choices
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("First option missing from OAI prompt return"))?
+        .message;
+
+    if let Some(call) = message.tool_calls.and_then(|c| c.into_iter().next()) {
+        return Ok(call.function.arguments);
+    }
+
+    message.content.ok_or_else(|| anyhow!("Content missing from OAI prompt message"))
+}
+
+fn to_openai_messages(messages: &[ChatMessage]) -> Result<Vec<ChatCompletionRequestMessage>> {
+    let mut res = vec![];
+
+    for m in messages {
+        let message: ChatCompletionRequestMessage = match m.role {
+            ChatRole::System => ChatCompletionRequestSystemMessageArgs::default()
+                .content(m.content.clone())
+                .build()?
+                .into(),
+            ChatRole::User => ChatCompletionRequestUserMessageArgs::default()
+                .content(m.content.clone())
+                .build()?
+                .into(),
+            ChatRole::Assistant => ChatCompletionRequestAssistantMessageArgs::default()
+                .content(m.content.clone())
+                .build()?
+                .into(),
+        };
+        res.push(message);
+    }
+
+    Ok(res)
+}
@@ -1,104 +1,68 @@
+use std::sync::Arc;
 use anyhow::{Result, anyhow, bail};
-use data::models::oai::{Prompt, ModelProvider};
+use data::models::oai::Prompt;
 use serde::de::DeserializeOwned;
-use dotenv::dotenv;
+use serde_json::Value;
+use schemars::JsonSchema;
+use jsonschema::Validator;
 
-use async_openai::{
-    types::{
-        CreateChatCompletionRequestArgs, ChatCompletionResponseFormat, ChatCompletionResponseFormatType, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
-    },
-    Client, config::{Config, OpenAIConfig},
-};
+use crate::llm::{LlmClient, ChatMessage, ResponseSchema};
 
 
 #[derive(Clone, Debug)]
 pub struct ChatAsync {
-    messages: Vec<ChatCompletionRequestMessage>,
-    model: String,
-    client: Client<OpenAIConfig>,
-    provider: ModelProvider
+    messages: Vec<ChatMessage>,
+    client: Arc<dyn LlmClient>
 }
 
 impl ChatAsync {
-    pub fn new(prompt: Prompt) -> Self {
-        dotenv().ok();
-        
-        let messages: Vec<ChatCompletionRequestMessage> = vec![
-            ChatCompletionRequestSystemMessageArgs::default()
-                .content(prompt.system_prompt)
-                .build().expect("Failed to build system request")
-                .into(),
-            ChatCompletionRequestUserMessageArgs::default()
-                .content(prompt.user_prompt)
-                .build().expect("Failed to build user request")
-                .into(),
-        ]; 
-        
-        let client = match prompt.provider {
-            ModelProvider::OpenAi => {
-                let key = std::env::var("OPENAI_API_KEY").expect("Could not find OPENAI_API_KEY");
-                let config = OpenAIConfig::new()
-                    .with_api_key(key);
-
-                let client = Client::with_config(config);
-                client
-            },
-            ModelProvider::Mistral => {
-                let key = std::env::var("MISTRAL_API_KEY").expect("Could not find MISTRAL_API_KEY");
-                let config = OpenAIConfig::new()
-                    .with_api_key(key)
-                    .with_api_base("https://api.mistral.ai/v1");
-
-                let client = Client::with_config(config);
-                client
-            },
-        };
+    pub fn new(client: Arc<dyn LlmClient>, prompt: Prompt) -> Self {
+        let messages = vec![
+            ChatMessage::system(prompt.system_prompt),
+            ChatMessage::user(prompt.user_prompt),
+        ];
 
         ChatAsync {
             messages,
-            model: prompt.model.to_string(),
-            client,
-            provider: prompt.provider
+            client
         }
     }
-    
 
-    pub async fn chat_json<P>(&mut self) -> Result<P> 
-    where 
-        P: DeserializeOwned
+
+    /// Asks for JSON matching `P`'s schema, which is also handed to the provider for its structured output.
+    /// Output that doesn't parse or match is sent back to the model with every problem found, so the retry
+    /// fixes those instead of guessing what went wrong.
+    pub async fn chat_json<P>(&mut self) -> Result<P>
+    where
+        P: DeserializeOwned + JsonSchema
     {
+        let schema = ResponseSchema::for_type::<P>()?;
+        let validator = jsonschema::validator_for(&schema.schema)
+            .map_err(|e| anyhow!("Invalid JSON schema for the chat response: {}", e))?;
+
+        let prompt_len = self.messages.len();
         let mut retry_count = 0;
         let max_retries = 5;
 
         while retry_count < max_retries {
-            match self.inner_chat_json().await {
-                Ok(result) => return Ok(result),
+            let returned_message = match self.client.chat_json(&self.messages, &schema).await {
+                Ok(m) => m,
                 Err(e) => {
-                    eprintln!("JSON Chat failure or deserialization error | error: {}. Retrying... ({}/{})", e, retry_count + 1, max_retries);
-
-                    // only want to system modify on first failure and not always
-                    if retry_count == 0 {
-                        let system_prompt = &self.messages.first()
-                            .ok_or_else(|| anyhow!("Failed to find system prompt when generating chat completion in error retry"))?
-                            .clone();
-
-                        let new_system_prompt = format!("
-                            You are being invoked again as a result of a JSON deserilazation failure in a previous attempt.
-                            Please pay careful attention to the JSON format described and adhere perfectly to this.
-                            
-                            {:?}
-                        ", system_prompt);
-
-                        let new_system_prompt = ChatCompletionRequestSystemMessage {
-                            content: new_system_prompt,
-                            role: async_openai::types::Role::System,
-                            name: None
-                        };
+                    eprintln!("JSON Chat failure | error: {}. Retrying... ({}/{})", e, retry_count + 1, max_retries);
+                    retry_count += 1;
+                    continue;
+                }
+            };
 
-                        let message = ChatCompletionRequestMessage::System(new_system_prompt);
+            match parse_json(&validator, &returned_message) {
+                Ok(res) => return Ok(res),
+                Err(problems) => {
+                    eprintln!("Invalid JSON response | problems: {} | response: {}. Retrying... ({}/{})", problems.join("; "), returned_message, retry_count + 1, max_retries);
 
-                        self.messages[0] = message;
-                    }
+                    // only the latest attempt is kept, earlier broken output would just add noise
+                    self.messages.truncate(prompt_len);
+                    self.messages.push(ChatMessage::assistant(returned_message));
+                    self.messages.push(ChatMessage::user(repair_prompt(&problems)));
                     retry_count += 1;
                 }
             }
@@ -107,60 +71,12 @@ impl ChatAsync {
         bail!("Failed to generate chat completion, error. Retried: {} times, giving up", retry_count);
     }
 
-    async fn inner_chat_json<P>(&self) -> Result<P> 
-    where 
-        P: DeserializeOwned
-    {
-        let client = &self.client;
-
-        let request = match &self.provider {
-            ModelProvider::OpenAi => {
-                let res_format = ChatCompletionResponseFormat {
-                    r#type: ChatCompletionResponseFormatType::JsonObject
-                };
-                
-                CreateChatCompletionRequestArgs::default()
-                    .stream(false)
-                    .model(&self.model)
-                    .response_format(res_format)
-                    .messages(self.messages.clone())
-                    .build()?
-            },
-            ModelProvider::Mistral => {
-                CreateChatCompletionRequestArgs::default()
-                    .stream(false)
-                    .model(&self.model)
-                    .messages(self.messages.clone())
-                    .build()?
-            }
-        };
-
-        let returned_message = client.chat().create(request).await?
-            .choices
-            .first()
-            .ok_or_else(|| anyhow!("First option missing from OAI prompt return"))?
-            .message
-            .content
-            .clone()
-            .ok_or_else(|| anyhow!("Content missing from OAI prompt message"))?;
-        
-        let returned_message = returned_message.replace("\\", "");
-
-        match serde_json::from_str::<P>(returned_message.as_str()) {
-            Ok(des) => Ok(des),
-            Err(e) => {
-                println!("JSON String: {}", returned_message);
-                bail!("Failed to deserialize JSON, error: {}", e)
-            }
-        }
-    }
-
     pub async fn chat_raw(&mut self) -> Result<String> {
         let mut retry_count = 0;
         let max_retries = 5;
 
         while retry_count < max_retries {
-            match self.inner_chat_raw().await {
+            match self.client.chat_raw(&self.messages).await {
                 Ok(result) => return Ok(result),
                 Err(e) => {
                     eprintln!("Raw Chat failure | error: {}. Retrying... ({}/{})", e, retry_count + 1, max_retries);
@@ -169,23 +85,15 @@ impl ChatAsync {
                     if retry_count == 0 {
                         let system_prompt = &self.messages.first()
                             .ok_or_else(|| anyhow!("Failed to find system prompt when generating chat completion in error retry"))?
-                            .clone();
+                            .content;
 
                         let new_system_prompt = format!("
                             You are being invoked as a result of a previous inference failure. Please review the system prompt carefully and response accurately.
-                            
-                            {:?}
-                        ", system_prompt);
 
-                        let new_system_prompt = ChatCompletionRequestSystemMessage {
-                            content: new_system_prompt,
-                            role: async_openai::types::Role::System,
-                            name: None
-                        };
-
-                        let message = ChatCompletionRequestMessage::System(new_system_prompt);
+                            {}
+                        ", system_prompt);
 
-                        self.messages[0] = message;
+                        self.messages[0] = ChatMessage::system(new_system_prompt);
                     }
                     retry_count += 1;
                 }
@@ -195,164 +103,142 @@ impl ChatAsync {
         bail!("Failed to generate chat completion, error. Retried: {} times, giving up", retry_count);
     }
 
-    async fn inner_chat_raw(&self) -> Result<String> {
-        let client = &self.client;
-        let request = CreateChatCompletionRequestArgs::default()
-            .stream(false)
-            .model(&self.model)
-            .temperature(0.2)
-            .messages(self.messages.clone())
-            .build()?;
-
-        Ok(client.chat().create(request).await?
-            .choices
-            .first()
-            .ok_or_else(|| anyhow!("First option missing from OAI prompt return"))?
-            .message
-            .content
-            .clone()
-            .ok_or_else(|| anyhow!("Content missing from OAI prompt message"))?)
-    }
-
 }
 
+/// The syntax error when the output isn't JSON, otherwise every schema violation with the path to the value
+fn parse_json<P: DeserializeOwned>(validator: &Validator, output: &str) -> Result<P, Vec<String>> {
+    let value: Value = serde_json::from_str(output)
+        .map_err(|e| vec![format!("Not valid JSON: {}", e)])?;
 
-#[deprecated]
-pub async fn chat_json<P>(mut messages: Vec<ChatCompletionRequestMessage>, model: &str) -> Result<P> 
-where 
-    P: DeserializeOwned
-{
-    let mut retry_count = 0;
-    let max_retries = 5;
-
-    while retry_count < max_retries {
-        match inner_chat_json(&messages, model).await {
-            Ok(result) => return Ok(result),
-            Err(e) => {
-                eprintln!("JSON Chat failure or deserialization error | error: {}. Retrying... ({}/{})", e, retry_count + 1, max_retries);
-
-                // only want to system modify on first failure and not always
-                if retry_count == 0 {
-                    let system_prompt = messages.first()
-                        .ok_or_else(|| anyhow!("Failed to find system prompt when generating chat completion in error retry"))?
-                        .clone();
-
-                    let new_system_prompt = format!("
-                        You are being invoked again as a result of a JSON deserilazation failure in a previous attempt.
-                        Please pay careful attention to the JSON format described and adhere perfectly to this.
-                        
-                        {:?}
-                    ", system_prompt);
-
-                    let new_system_prompt = ChatCompletionRequestSystemMessage {
-                        content: new_system_prompt,
-                        role: async_openai::types::Role::System,
-                        name: None
-                    };
-
-                    let message = ChatCompletionRequestMessage::System(new_system_prompt);
-
-                    messages[0] = message;
-                }
-                retry_count += 1;
-            }
-        }
+    let problems = validator.iter_errors(&value)
+        .map(|e| match e.instance_path.to_string().as_str() {
+            "" => e.to_string(),
+            path => format!("{}: {}", path, e)
+        })
+        .collect::<Vec<String>>();
+
+    if !problems.is_empty() {
+        return Err(problems);
     }
 
-    bail!("Failed to generate chat completion, error. Retried: {} times, giving up", retry_count);
+    serde_json::from_value(value).map_err(|e| vec![e.to_string()])
 }
 
-async fn inner_chat_json<P>(messages: &Vec<ChatCompletionRequestMessage>, model: &str) -> Result<P> 
-where 
-    P: DeserializeOwned
-{
-    let client = Client::new();
-    let res_format = ChatCompletionResponseFormat {
-        r#type: ChatCompletionResponseFormatType::JsonObject
-    };
-
-    let request = CreateChatCompletionRequestArgs::default()
-        .stream(false)
-        .model(model)
-        // .temperature(0.2)
-        .response_format(res_format)
-        .messages(messages.clone())
-        .build()?;
-
-    let returned_message = client.chat().create(request).await?
-        .choices
-        .first()
-        .ok_or_else(|| anyhow!("First option missing from OAI prompt return"))?
-        .message
-        .content
-        .clone()
-        .ok_or_else(|| anyhow!("Content missing from OAI prompt message"))?;
-
-    match serde_json::from_str::<P>(returned_message.as_str()) {
-        Ok(des) => Ok(des),
-        Err(e) => {
-            bail!("Failed to deserialize JSON, error: {}", e)
-        }
-    }
+fn repair_prompt(problems: &[String]) -> String {
+    let problems = problems.iter()
+        .map(|p| format!("- {}", p))
+        .collect::<Vec<String>>()
+        .join("\n");
+
+    format!("
+        Your previous response doesn't match the required JSON format:
+        {}
+
+        Respond with the corrected JSON object only, NO OTHER WORDS.
+    ", problems)
 }
 
 
-#[deprecated]
-pub async fn chat_raw(mut messages: Vec<ChatCompletionRequestMessage>, model: &str) -> Result<String> {
-    let mut retry_count = 0;
-    let max_retries = 5;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::models::oai::NihongoWordOpenAiRes;
+    use crate::llm::mock::{MockLlmClient, MockResponse};
+
+    const WORD_JSON: &str = r#"{
+        "word": "食べる",
+        "is_kanji": true,
+        "word_reading": "たべる",
+        "definition": "to eat",
+        "sentence": "毎朝パンを食べる。",
+        "sentence_translation": "I eat bread every morning.",
+        "kanji_mnemonic": null,
+        "spoken_mnemonic": "Tabby cat eats",
+        "word_tenses": []
+    }"#;
+
+    fn prompt() -> Prompt {
+        Prompt {
+            system_prompt: "Respond with JSON".to_string(),
+            user_prompt: "Word: 食べる".to_string()
+        }
+    }
 
-    while retry_count < max_retries {
-        match inner_chat_raw(&messages, model).await {
-            Ok(result) => return Ok(result),
-            Err(e) => {
-                eprintln!("Raw Chat failure | error: {}. Retrying... ({}/{})", e, retry_count + 1, max_retries);
+    #[tokio::test]
+    async fn chat_json_retries_until_valid_json() {
+        let client = Arc::new(MockLlmClient::new().with_script(vec![
+            MockResponse::Err("connection reset".to_string()),
+            MockResponse::Ok("{\"word\": ".to_string()),
+            MockResponse::Ok(WORD_JSON.to_string()),
+        ]));
+
+        let res: NihongoWordOpenAiRes = ChatAsync::new(client.clone(), prompt()).chat_json().await.unwrap();
+        assert_eq!(res.word, "食べる");
+        assert_eq!(res.kanji_mnemonic, None);
+
+        let calls = client.calls();
+        assert_eq!(calls.len(), 3);
+        // a failed request is retried as is
+        assert_eq!(calls[0], calls[1]);
+        assert_eq!(calls[2].len(), 4);
+        assert_eq!(calls[2][2], ChatMessage::assistant("{\"word\": "));
+        assert!(calls[2][3].content.contains("Not valid JSON: EOF while parsing"));
+    }
 
-                // only want to system modify on first failure and not always
-                if retry_count == 0 {
-                    let system_prompt = messages.first()
-                        .ok_or_else(|| anyhow!("Failed to find system prompt when generating chat completion in error retry"))?
-                        .clone();
+    #[tokio::test]
+    async fn chat_json_sends_schema_errors_back() {
+        let invalid = WORD_JSON
+            .replace(r#""definition": "to eat","#, "")
+            .replace(r#""word_tenses": []"#, r#""word_tenses": [{ "word": "食べた", "sentence": 1, "tense_type": "" }]"#);
+        let client = Arc::new(MockLlmClient::new().with_script(vec![
+            MockResponse::Ok(invalid.clone()),
+            MockResponse::Ok("not json".to_string()),
+            MockResponse::Ok(WORD_JSON.to_string()),
+        ]));
+
+        let res: NihongoWordOpenAiRes = ChatAsync::new(client.clone(), prompt()).chat_json().await.unwrap();
+        assert_eq!(res.definition, "to eat");
+
+        let calls = client.calls();
+        assert_eq!(calls[1][2], ChatMessage::assistant(invalid));
+        let repair = &calls[1][3].content;
+        assert!(repair.contains(r#"- "definition" is a required property"#), "{}", repair);
+        assert!(repair.contains("- /word_tenses/0/sentence: 1 is not of type \"string\""), "{}", repair);
+        assert!(repair.contains("- /word_tenses/0/tense_type: \"\" is shorter than 1 character"), "{}", repair);
+
+        // only the latest broken output is sent back
+        assert_eq!(calls[2].len(), 4);
+        assert_eq!(calls[2][2], ChatMessage::assistant("not json"));
+    }
 
-                    let new_system_prompt = format!("
-                        You are being invoked as a result of a previous inference failure. Please review the system prompt carefully and response accurately.
-                        
-                        {:?}
-                    ", system_prompt);
+    #[tokio::test]
+    async fn chat_json_keeps_escapes() {
+        let escaped = WORD_JSON.replace("I eat bread every morning.", r#"I \"eat\" bread\nevery morning."#);
+        let client = Arc::new(MockLlmClient::new().with_script(vec![MockResponse::Ok(escaped)]));
 
-                    let new_system_prompt = ChatCompletionRequestSystemMessage {
-                        content: new_system_prompt,
-                        role: async_openai::types::Role::System,
-                        name: None
-                    };
+        let res: NihongoWordOpenAiRes = ChatAsync::new(client, prompt()).chat_json().await.unwrap();
+        assert_eq!(res.sentence_translation, "I \"eat\" bread\nevery morning.");
+    }
 
-                    let message = ChatCompletionRequestMessage::System(new_system_prompt);
+    #[tokio::test]
+    async fn chat_json_gives_up_after_max_retries() {
+        let script = (0..5).map(|_| MockResponse::Ok("not json".to_string())).collect();
+        let client = Arc::new(MockLlmClient::new().with_script(script).with_fixture(&[], WORD_JSON));
 
-                    messages[0] = message;
-                }
-                retry_count += 1;
-            }
-        }
+        let res = ChatAsync::new(client.clone(), prompt()).chat_json::<NihongoWordOpenAiRes>().await;
+        assert!(res.unwrap_err().to_string().contains("Retried: 5 times"));
+        assert_eq!(client.calls().len(), 5);
     }
 
-    bail!("Failed to generate chat completion, error. Retried: {} times, giving up", retry_count);
-}
+    #[tokio::test]
+    async fn chat_raw_retries_on_failure() {
+        let client = Arc::new(MockLlmClient::new().with_script(vec![
+            MockResponse::Err("timeout".to_string()),
+            MockResponse::Ok("ok".to_string()),
+        ]));
 
-async fn inner_chat_raw(messages: &Vec<ChatCompletionRequestMessage>, model: &str) -> Result<String> {
-    let client = Client::new();
-    let request = CreateChatCompletionRequestArgs::default()
-        .stream(false)
-        .model(model)
-        .temperature(0.2)
-        .messages(messages.clone())
-        .build()?;
-
-    Ok(client.chat().create(request).await?
-        .choices
-        .first()
-        .ok_or_else(|| anyhow!("First option missing from OAI prompt return"))?
-        .message
-        .content
-        .clone()
-        .ok_or_else(|| anyhow!("Content missing from OAI prompt message"))?)
+        let res = ChatAsync::new(client.clone(), prompt()).chat_raw().await.unwrap();
+        assert_eq!(res, "ok");
+        assert!(client.calls()[1][0].content.contains("previous inference failure"));
+    }
 }
@@ -0,0 +1,339 @@
+use std::{collections::BTreeMap, path::{Path, PathBuf}};
+use anyhow::{Result, Context, bail};
+use clap::{Parser, Subcommand};
+use serde::Deserialize;
+
+use crate::template;
+
+/// Used when `--config` isn't given and the file exists in the working directory
+const DEFAULT_CONFIG_PATH: &str = "worker.toml";
+
+#[derive(Debug, Default, Parser)]
+#[command(version, about = "Generates audio for saved words and adds them to anki")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// TOML config file, defaults to `worker.toml` when it exists
+    #[arg(long, short, env = "WORKER_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "ANKI_CONNECT_URL")]
+    pub anki_url: Option<String>,
+    #[arg(long, env = "ANKI_CONNECT_KEY", hide_env_values = true)]
+    pub anki_key: Option<String>,
+    #[arg(long, env = "ANKI_DECK")]
+    pub deck: Option<String>,
+    #[arg(long, env = "ANKI_NOTE_TYPE")]
+    pub note_type: Option<String>,
+    #[arg(long, env = "ANKI_MEDIA_DIR")]
+    pub media_dir: Option<PathBuf>,
+    #[arg(long, env = "ELEVEN_LABS_VOICE_ID")]
+    pub voice_id: Option<String>,
+    #[arg(long, env = "WORKER_POLL_INTERVAL_SECS")]
+    pub poll_interval_secs: Option<u64>,
+    #[arg(long, env = "WORKER_LOG_DIR")]
+    pub log_dir: Option<PathBuf>
+}
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Command {
+    /// Applies the migrations and exits
+    Migrate
+}
+
+/// Worker settings, from the config file, then env vars, then cli flags, each overriding the one before
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerConfig {
+    pub worker: WorkerSettings,
+    pub anki: AnkiSettings,
+    pub tts: TtsSettings,
+    /// Note field name to the template of its content, see `template::VARIABLES`.
+    /// A `[fields]` table in the file replaces these as a whole.
+    pub fields: BTreeMap<String, String>
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        WorkerConfig {
+            worker: WorkerSettings::default(),
+            anki: AnkiSettings::default(),
+            tts: TtsSettings::default(),
+            fields: default_fields()
+        }
+    }
+}
+
+/// The fields of the `JP1Kv3` note type
+fn default_fields() -> BTreeMap<String, String> {
+    [
+        ("Index", "{{index}}"),
+        ("Word", "{{word}}"),
+        ("Word With Reading", "{{reading}}"),
+        ("Definition", "{{definition}}"),
+        ("Example Sentence", "{{sentences}}"),
+        ("Sentence Translation", "{{sentence_translations}}"),
+        ("word_audio", "{{word_audio}}"),
+        ("sentence_audio", "{{sentence_audio}}"),
+        ("Kanji", "{{is_kanji}}"),
+        ("kanji_mnemonic", "{{kanji_mnemonic}}"),
+        ("spoken_mnemonic", "{{spoken_mnemonic}}"),
+        ("tenses", "{{tenses}}")
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerSettings {
+    /// How long to sleep between runs
+    pub poll_interval_secs: u64,
+    pub log_dir: PathBuf
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        WorkerSettings {
+            poll_interval_secs: 86400,
+            log_dir: "/var/log/langcrack".into()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnkiSettings {
+    pub url: String,
+    /// Only needed when `apiKey` is set in the AnkiConnect config
+    pub key: Option<String>,
+    pub deck: String,
+    pub note_type: String,
+    /// Anki's `collection.media` folder, only written to when AnkiConnect can't be reached
+    pub media_dir: Option<PathBuf>
+}
+
+impl Default for AnkiSettings {
+    fn default() -> Self {
+        AnkiSettings {
+            url: "http://localhost:8765".to_string(),
+            key: None,
+            deck: "Dan's Nihongo Deck".to_string(),
+            note_type: "JP1Kv3".to_string(),
+            media_dir: None
+        }
+    }
+}
+
+/// ElevenLabs text to speech
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TtsSettings {
+    /// Prefer `ELEVEN_LABS_KEY` over putting the key in the file
+    pub api_key: Option<String>,
+    pub voice_id: String,
+    pub model_id: String,
+    pub stability: f32,
+    pub similarity_boost: f32
+}
+
+impl Default for TtsSettings {
+    fn default() -> Self {
+        TtsSettings {
+            api_key: None,
+            voice_id: "IKne3meq5aSn9XLyUdCD".to_string(),
+            model_id: "eleven_multilingual_v2".to_string(),
+            stability: 0.5,
+            similarity_boost: 0.7
+        }
+    }
+}
+
+impl WorkerConfig {
+    /// Reads the config file (when there is one) and applies `ELEVEN_LABS_KEY` and the env / cli overrides in `args`
+    pub fn load(args: &Args) -> Result<Self> {
+        let path = match &args.config {
+            Some(p) => Some(p.clone()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_PATH)).filter(|p| p.exists())
+        };
+
+        let mut config = match path {
+            Some(p) => WorkerConfig::from_file(&p)?,
+            None => WorkerConfig::default()
+        };
+
+        if let Ok(key) = std::env::var("ELEVEN_LABS_KEY") {
+            config.tts.api_key = Some(key).filter(|k| !k.is_empty());
+        }
+        config.apply(args);
+
+        Ok(config)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read worker config: {}", path.display()))?;
+
+        toml::from_str(&content)
+            .with_context(|| format!("Failed to parse worker config: {}", path.display()))
+    }
+
+    /// The field holding just the word, existing notes are found by it
+    pub fn word_field(&self) -> Option<&str> {
+        self.fields.iter()
+            .find(|(_, t)| t.split_whitespace().collect::<String>() == "{{word}}")
+            .map(|(name, _)| name.as_str())
+    }
+
+    fn apply(&mut self, args: &Args) {
+        if let Some(v) = &args.anki_url {
+            self.anki.url = v.clone();
+        }
+        if let Some(v) = &args.anki_key {
+            self.anki.key = Some(v.clone());
+        }
+        if let Some(v) = &args.deck {
+            self.anki.deck = v.clone();
+        }
+        if let Some(v) = &args.note_type {
+            self.anki.note_type = v.clone();
+        }
+        if let Some(v) = &args.media_dir {
+            self.anki.media_dir = Some(v.clone());
+        }
+        if let Some(v) = &args.voice_id {
+            self.tts.voice_id = v.clone();
+        }
+        if let Some(v) = args.poll_interval_secs {
+            self.worker.poll_interval_secs = v;
+        }
+        if let Some(v) = &args.log_dir {
+            self.worker.log_dir = v.clone();
+        }
+    }
+
+    /// Every problem at once, so a broken config is fixed in one go instead of one restart per mistake
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+
+        if self.worker.poll_interval_secs == 0 {
+            problems.push("worker.poll_interval_secs must be greater than 0".to_string());
+        }
+        if let Err(e) = reqwest::Url::parse(&self.anki.url) {
+            problems.push(format!("anki.url is not a valid url: {}", e));
+        }
+        if let Some(dir) = self.anki.media_dir.as_ref().filter(|d| !d.is_dir()) {
+            problems.push(format!("anki.media_dir is not an existing directory: {}", dir.display()));
+        }
+        for (name, v) in [("anki.deck", &self.anki.deck), ("anki.note_type", &self.anki.note_type), ("tts.voice_id", &self.tts.voice_id), ("tts.model_id", &self.tts.model_id)] {
+            if v.trim().is_empty() {
+                problems.push(format!("{} must not be empty", name));
+            }
+        }
+        if self.tts.api_key.is_none() {
+            problems.push("tts.api_key is missing, set ELEVEN_LABS_KEY".to_string());
+        }
+        for (name, v) in [("tts.stability", self.tts.stability), ("tts.similarity_boost", self.tts.similarity_boost)] {
+            if !(0.0..=1.0).contains(&v) {
+                problems.push(format!("{} must be between 0 and 1, got {}", name, v));
+            }
+        }
+
+        for (name, t) in &self.fields {
+            match template::variables(t) {
+                Ok(vars) => problems.extend(vars.into_iter()
+                    .filter(|v| !template::VARIABLES.contains(v))
+                    .map(|v| format!("fields.{}: unknown variable {}", name, v))),
+                Err(e) => problems.push(format!("fields.{}: {}", name, e))
+            }
+        }
+        if self.word_field().is_none() {
+            problems.push("fields needs a field set to {{word}}, notes are looked up by it".to_string());
+        }
+
+        if !problems.is_empty() {
+            bail!("Invalid worker config | {}", problems.join("; "));
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_overrides_defaults_and_args_override_file() {
+        let mut config: WorkerConfig = toml::from_str(r#"
+            [anki]
+            deck = "Kaishi 1.5k"
+            url = "http://anki:8765"
+
+            [tts]
+            api_key = "key"
+            stability = 0.3
+        "#).unwrap();
+        assert_eq!(config.anki.deck, "Kaishi 1.5k");
+        assert_eq!(config.anki.note_type, "JP1Kv3");
+        assert_eq!(config.tts.stability, 0.3);
+        assert_eq!(config.worker, WorkerSettings::default());
+        config.validate().unwrap();
+
+        assert_eq!(config.anki.media_dir, None);
+
+        let args = Args::try_parse_from(["worker", "--deck", "Core 2k", "--poll-interval-secs", "60", "--media-dir", "/tmp", "migrate"]).unwrap();
+        assert_eq!(args.command, Some(Command::Migrate));
+        config.apply(&args);
+        assert_eq!(config.anki.deck, "Core 2k");
+        assert_eq!(config.anki.url, "http://anki:8765");
+        assert_eq!(config.worker.poll_interval_secs, 60);
+        assert_eq!(config.anki.media_dir, Some(PathBuf::from("/tmp")));
+    }
+
+    #[test]
+    fn fields_replace_the_default_mapping() {
+        let mut config: WorkerConfig = toml::from_str(r#"
+            [tts]
+            api_key = "key"
+
+            [fields]
+            Vocab = "{{ word }}"
+            Reading = "{{word}}[{{reading}}]"
+        "#).unwrap();
+        assert_eq!(config.fields.len(), 2);
+        assert_eq!(config.word_field(), Some("Vocab"));
+        config.validate().unwrap();
+
+        assert_eq!(WorkerConfig::default().word_field(), Some("Word"));
+
+        config.fields = BTreeMap::from([("Front".to_string(), "{{word}} {{furigana}} {{reading".to_string())]);
+        assert_eq!(config.validate().unwrap_err().to_string(), "Invalid worker config | \
+            fields.Front: Unclosed {{ in template: {{word}} {{furigana}} {{reading; \
+            fields needs a field set to {{word}}, notes are looked up by it");
+
+        config.fields = BTreeMap::from([("Front".to_string(), "{{word}} {{furigana}}".to_string())]);
+        assert!(config.validate().unwrap_err().to_string().contains("fields.Front: unknown variable furigana"));
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        assert!(toml::from_str::<WorkerConfig>("[anki]\ndek = \"typo\"").is_err());
+
+        let mut config = WorkerConfig::default();
+        config.anki.url = "localhost".to_string();
+        config.anki.deck = " ".to_string();
+        config.tts.similarity_boost = 1.5;
+        config.anki.media_dir = Some("/nonexistent/collection.media".into());
+        config.worker.poll_interval_secs = 0;
+
+        assert_eq!(config.validate().unwrap_err().to_string(), "Invalid worker config | \
+            worker.poll_interval_secs must be greater than 0; \
+            anki.url is not a valid url: relative URL without a base; \
+            anki.media_dir is not an existing directory: /nonexistent/collection.media; \
+            anki.deck must not be empty; \
+            tts.api_key is missing, set ELEVEN_LABS_KEY; \
+            tts.similarity_boost must be between 0 and 1, got 1.5");
+    }
+}
@@ -1,66 +1,99 @@
-use anyhow::{Result, bail};
-use serde_json::Value;
+mod config;
+mod template;
+mod note_type;
+
+use anyhow::{Result, anyhow};
+use clap::Parser;
 use tokio::time::{sleep, Duration};
-use reqwest::Client;
 use dotenv::dotenv;
 use rand::Rng;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
 
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
-use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap};
 
 
 use data::{
-    models::db::NihongoWordWithTenses,
-    services::data::{get_unprocessed_words, update_word_status}
+    models::db::{NihongoWordWithTenses, NihongoWordSentence, AUDIO_STATUS_GENERATED, AUDIO_STATUS_FAILED},
+    services::store::{self, WordStore}
 };
+use services::anki::{AnkiClient, NewNote, NoteOptions, DuplicateScopeOptions, MediaFile};
 
-const VOICE_ID: &str = "IKne3meq5aSn9XLyUdCD";
-const DECK_NAME: &str = "Dan's Nihongo Deck";
-const DECK_FORMAT: &str = "JP1Kv3";
-const BASE_ANKI_MEDIA_DIR: &str = "/home/dan/.local/share/Anki2/User 1/collection.media";
-const SLEEP_TIME: u64 = 86400;
+use crate::config::{Args, Command, WorkerConfig, TtsSettings};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let file_appender = tracing_appender::rolling::daily("/var/log/langcrack", "lang_crack.log");
+    // before parsing so `.env` can set the env backed flags
+    dotenv().ok();
+    let args = Args::parse();
+    let config = WorkerConfig::load(&args)?;
+
+    let file_appender = tracing_appender::rolling::daily(&config.worker.log_dir, "lang_crack.log");
     tracing_subscriber::fmt().with_writer(file_appender).init();
 
     tracing::info!("Starting worker v0.1");
 
-    dotenv().ok();
-    let eleven_labs_key = std::env::var("ELEVEN_LABS_KEY")?;
+    let db = store::from_env().await?;
+
+    // `worker migrate` applies the migrations and exits
+    if args.command == Some(Command::Migrate) {
+        db.migrate().await?;
+        tracing::info!("Migrations applied");
+        return Ok(());
+    }
+
+    config.validate()?;
+
+    if store::run_migrations_on_startup() {
+        db.migrate().await?;
+    }
+
+    let anki = AnkiClient::new(Some(&config.anki.url), config.anki.key.as_deref());
+    let media = MediaStore {
+        anki: anki.clone(),
+        fallback_dir: config.anki.media_dir.clone()
+    };
+    let sleep_time = Duration::from_secs(config.worker.poll_interval_secs);
+
+    anki.check_version().await?;
+    note_type::ensure_deck_and_note_type(&anki, &config).await?;
 
     loop {
-        let up_words = get_unprocessed_words().await?;
-        let words = group_rows(up_words);
+        let up_words = db.get_unprocessed_words().await?;
+        let mut words = group_rows(up_words);
 
-        if words.len() == 0 {
+        if words.is_empty() {
             tracing::info!("No words to process");
-            sleep(Duration::from_secs(SLEEP_TIME)).await;
+            sleep(sleep_time).await;
             continue;
         }
 
+        anki.check_version().await?;
+
         tracing::info!("Syncing Anki - Before processing words");
-        sync_anki().await?;
+        anki.sync().await?;
 
-        for w in &words {
+        for w in &mut words {
             tracing::info!("Processing word: {}", w.word);
 
-            generate_and_save_audio_files(&w.word, &w.word_reading, &w.sentence, &eleven_labs_key).await?;
-            add_card_anki(w).await?;
+            w.sentences = db.get_word_sentences(w.id).await?;
+
+            generate_and_save_audio_files(&media, &config.tts, &w.word, &w.word_reading, &w.sentence).await?;
+            generate_sentence_audio_files(&media, &config.tts, db.as_ref(), w).await?;
+            upsert_card_anki(&anki, &config, w).await?;
 
             // finally update the word status to processed = true
-            update_word_status(w.id).await?;
+            db.update_word_status(w.id).await?;
             tracing::info!("Processed word: {}", w.word);
         }
 
         tracing::info!("Syncing Anki - After processing words");
-        sync_anki().await?;
+        anki.sync().await?;
 
-        tracing::info!("Sleeping for 24 hours");
-        sleep(Duration::from_secs(SLEEP_TIME)).await;
+        tracing::info!("Sleeping for {} seconds", config.worker.poll_interval_secs);
+        sleep(sleep_time).await;
     }
 }
 
@@ -72,14 +105,17 @@ fn group_rows(words: Vec<NihongoWordWithTenses>) -> Vec<NihongoWordsGrouped> {
             .or_insert(NihongoWordsGrouped { 
                 id: w.id, 
                 word: w.word.clone(), 
-                is_kanji: w.is_kanji.clone(), 
+                is_kanji: w.is_kanji, 
                 definition: w.definition.clone(), 
                 sentence: w.sentence.clone(), 
                 kanji_mnemonic: w.kanji_mnemonic.clone(),
                 spoken_mnemonic: w.spoken_mnemonic.clone(), 
                 word_reading: w.word_reading.clone(), 
                 sentence_translation: w.sentence_translation.clone(), 
-                tenses: vec![] 
+                tenses: vec![],
+                sentences: vec![],
+                source_title: w.source_title.clone(),
+                source_url: w.source_url.clone()
             })
             .tenses.push(NihongoWordTense { tense_word: w.tense_word.clone(), tense_sentence: w.tense_sentence.clone(), tense_type: w.tense_type.clone() })
     }
@@ -90,41 +126,100 @@ fn group_rows(words: Vec<NihongoWordWithTenses>) -> Vec<NihongoWordsGrouped> {
 }
 
 async fn generate_and_save_audio_files(
+    media: &MediaStore,
+    tts: &TtsSettings,
     word: &str, 
     word_reading: &str, 
-    sentence: &str,
-    eleven_labs_key: &str
+    sentence: &str
 ) -> Result<()> {
-    let sentence_audio = generate_audio(eleven_labs_key, sentence).await?;
-    save_file(word, word_reading, sentence_audio, "sentence")?;
+    let sentence_audio = generate_audio(tts, sentence).await?;
+    save_file(media, word, word_reading, sentence_audio, "sentence").await?;
 
-    let word_audio = generate_audio(eleven_labs_key, word_reading).await?;
-    save_file(word, word_reading, word_audio, "word")?;
+    let word_audio = generate_audio(tts, word_reading).await?;
+    save_file(media, word, word_reading, word_audio, "word").await?;
 
     Ok(())
 }
 
-/// file_type is really just 'word' or 'sentence' but I didn't feel like enum'ing it
-fn save_file(word: &str, word_reading: &str, bytes: Vec<u8>, file_type: &str) -> Result<()> {
-    let path = format!("{}/lang_crack_audio_{}_{}_{}.mp3", BASE_ANKI_MEDIA_DIR, file_type, word, word_reading);
-    let path = Path::new(&path);
-    save_mp3(bytes, path)?;
+/// Audio for the extra sentences is only generated once, a sentence that failed is retried the next time the word is processed
+async fn generate_sentence_audio_files(media: &MediaStore, tts: &TtsSettings, db: &dyn WordStore, word: &mut NihongoWordsGrouped) -> Result<()> {
+    for s in word.sentences.iter_mut().filter(|s| s.audio_status != AUDIO_STATUS_GENERATED) {
+        let res = match generate_audio(tts, &s.sentence).await {
+            Ok(audio) => save_file(media, &word.word, &word.word_reading, audio, &format!("sentence_{}", s.id)).await,
+            Err(e) => Err(e)
+        };
+
+        s.audio_status = match res {
+            Ok(_) => AUDIO_STATUS_GENERATED.to_string(),
+            Err(e) => {
+                tracing::error!("Failed to generate sentence audio | word: {} | sentence id: {} | error: {}", word.word, s.id, e);
+                AUDIO_STATUS_FAILED.to_string()
+            }
+        };
+        db.update_sentence_audio_status(s.id, &s.audio_status).await?;
+    }
 
     Ok(())
 }
 
-async fn generate_audio(api_key: &str, text: &str) -> Result<Vec<u8>> {
-    let target = format!("https://api.elevenlabs.io/v1/text-to-speech/{}", VOICE_ID);
+/// file_type is really just 'word', 'sentence' or 'sentence_<id>' but I didn't feel like enum'ing it
+async fn save_file(media: &MediaStore, word: &str, word_reading: &str, bytes: Vec<u8>, file_type: &str) -> Result<()> {
+    let filename = format!("lang_crack_audio_{}_{}_{}.mp3", file_type, word, word_reading);
+    media.save(&filename, bytes).await
+}
+
+/// Where the audio files end up, anki's media folder
+struct MediaStore {
+    anki: AnkiClient,
+    /// Anki's `collection.media` folder, only written to when AnkiConnect can't be reached
+    fallback_dir: Option<PathBuf>
+}
+
+impl MediaStore {
+    /// Uploads the file through AnkiConnect so the worker doesn't need to run next to anki,
+    /// falls back to writing it straight into the media folder only when AnkiConnect can't be reached
+    async fn save(&self, filename: &str, bytes: Vec<u8>) -> Result<()> {
+        let file = MediaFile {
+            filename: filename.to_string(),
+            data: Some(BASE64.encode(&bytes)),
+            delete_existing: Some(true),
+            ..Default::default()
+        };
+
+        match self.anki.store_media_file(&file).await {
+            Ok(_) => Ok(()),
+            Err(e) => match &self.fallback_dir {
+                Some(dir) if is_unreachable(&e) => {
+                    tracing::warn!("AnkiConnect is unreachable, writing media to {} | file: {} | error: {}", dir.display(), filename, e);
+                    save_mp3(bytes, &dir.join(filename)).map_err(|io| anyhow!(
+                        "Failed to store media | file: {} | AnkiConnect error: {} | {} error: {}",
+                        filename, e, dir.display(), io
+                    ))
+                },
+                _ => Err(e)
+            }
+        }
+    }
+}
+
+/// Connection and timeout errors, as opposed to AnkiConnect answering with an error
+fn is_unreachable(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_connect() || e.is_timeout())
+}
+
+async fn generate_audio(tts: &TtsSettings, text: &str) -> Result<Vec<u8>> {
+    let target = format!("https://api.elevenlabs.io/v1/text-to-speech/{}", tts.voice_id);
     let client = reqwest::Client::new();
+    let api_key = tts.api_key.as_deref().ok_or_else(|| anyhow!("Missing ElevenLabs api key, set ELEVEN_LABS_KEY"))?;
 
     let bytes = client.post(target)
         .header("xi-api-key", api_key)
         .json(&serde_json::json!({
-              "model_id": "eleven_multilingual_v2",
+              "model_id": tts.model_id,
               "text": text,
               "voice_settings": {
-                "similarity_boost": 0.7,
-                "stability": 0.5
+                "similarity_boost": tts.similarity_boost,
+                "stability": tts.stability
               }
         }))
         .send()
@@ -141,13 +236,37 @@ fn save_mp3(bytes: Vec<u8>, path: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
-async fn add_card_anki(
-    word: &NihongoWordsGrouped
-) -> Result<()> {
+/// Words that were edited through the api are unprocessed again, so update their existing note rather than adding another one
+async fn upsert_card_anki(anki: &AnkiClient, config: &WorkerConfig, word: &NihongoWordsGrouped) -> Result<()> {
+    let word_field = config.word_field().ok_or_else(|| anyhow!("No field is set to {{{{word}}}} in the worker config"))?;
+
+    match find_note_anki(anki, &config.anki.deck, word_field, &word.word).await? {
+        Some(note_id) => {
+            tracing::info!("Updating existing note: {} | note id: {}", word.word, note_id);
+            update_card_anki(anki, config, note_id, word).await
+        },
+        None => add_card_anki(anki, config, word).await
+    }
+}
+
+/// Values of the `template::VARIABLES` except `index`
+fn word_values(word: &NihongoWordsGrouped) -> HashMap<&'static str, String> {
     let anki_word_ref = format!("[sound:lang_crack_audio_word_{}_{}.mp3]", word.word, word.word_reading);
-    let anki_sentence_ref = format!("[sound:lang_crack_audio_sentence_{}_{}.mp3]", word.word, word.word_reading);
-    let mut rng = rand::thread_rng();
-    let range = rng.gen_range(1001..20000);
+    let mut anki_sentence_ref = format!("[sound:lang_crack_audio_sentence_{}_{}.mp3]", word.word, word.word_reading);
+    for s in word.sentences.iter().filter(|s| s.audio_status == AUDIO_STATUS_GENERATED) {
+        anki_sentence_ref.push_str(&format!("[sound:lang_crack_audio_sentence_{}_{}_{}.mp3]", s.id, word.word, word.word_reading));
+    }
+
+    // every example sentence, the word's own one first
+    let sentences = std::iter::once(word.sentence.as_str())
+        .chain(word.sentences.iter().map(|s| s.sentence.as_str()))
+        .collect::<Vec<&str>>()
+        .join("<br>");
+    let sentence_translations = std::iter::once(word.sentence_translation.as_str())
+        .chain(word.sentences.iter().map(|s| s.sentence_translation.as_str()))
+        .collect::<Vec<&str>>()
+        .join("<br>");
+
     let is_kanji = match word.is_kanji {
         true => "True",
         false => "False"
@@ -155,7 +274,7 @@ async fn add_card_anki(
 
     let mut tenses = "".to_string();
     
-    if word.tenses.len() > 0 {
+    if !word.tenses.is_empty() {
         for t in &word.tenses {
             tenses.push_str(&format!("
                 {:?}: {:?} \n
@@ -166,75 +285,129 @@ async fn add_card_anki(
         }
     }
 
-    let res: Value = Client::new()
-        .post("http://localhost:8765")
-        .json(&serde_json::json!({
-            "action": "addNote",
-            "version": 6,
-            "params": {
-                "note": {
-                    "deckName": DECK_NAME,
-                    "modelName": DECK_FORMAT,
-                    "fields": {
-                        "Index": format!("{}", range),
-                        "Word": word.word,
-                        "Word With Reading": word.word_reading,
-                        "Definition": word.definition,
-                        "Example Sentence": word.sentence,
-                        "Sentence Translation": word.sentence_translation,
-                        "word_audio": anki_word_ref,
-                        "sentence_audio": anki_sentence_ref,
-                        "Kanji": is_kanji,
-                        "kanji_mnemonic": word.kanji_mnemonic,
-                        "spoken_mnemonic": word.spoken_mnemonic,
-                        "tenses": tenses
-                    },
-                   "options": {
-                        "allowDuplicate": false,
-                        "duplicateScope": "deck",
-                        "duplicateScopeOptions": {
-                            "deckName": "Default",
-                            "checkChildren": false,
-                            "checkAllModels": false
-                        }
-                    },
-                    "tags": [
-                        "lang-crack"
-                    ]
-                } 
-            }
-        }))
-        .send()
-        .await?
-        .json()
-        .await?;
+    HashMap::from([
+        ("word", word.word.clone()),
+        ("reading", word.word_reading.clone()),
+        ("definition", word.definition.clone()),
+        ("is_kanji", is_kanji.to_string()),
+        ("sentence", word.sentence.clone()),
+        ("sentence_translation", word.sentence_translation.clone()),
+        ("sentences", sentences),
+        ("sentence_translations", sentence_translations),
+        ("word_audio", anki_word_ref),
+        ("sentence_audio", anki_sentence_ref),
+        ("kanji_mnemonic", word.kanji_mnemonic.clone().unwrap_or_default()),
+        ("spoken_mnemonic", word.spoken_mnemonic.clone().unwrap_or_default()),
+        ("tenses", tenses),
+        ("source_title", word.source_title.clone().unwrap_or_default()),
+        ("source_url", word.source_url.clone().unwrap_or_default())
+    ])
+}
 
-    if !res["error"].is_null() {
-        bail!("Response from anki contained error(s) | error(s): {}", res["error"]) 
+/// Renders every field template of the config. Fields using `{{index}}` are only rendered when `index`
+/// is given, i.e. when the note is added, so an updated card keeps its place in the deck.
+fn note_fields(word: &NihongoWordsGrouped, templates: &BTreeMap<String, String>, index: Option<u32>) -> Result<BTreeMap<String, String>> {
+    let mut values = word_values(word);
+    if let Some(i) = index {
+        values.insert("index", i.to_string());
     }
 
+    let mut fields = BTreeMap::new();
+    for (name, t) in templates {
+        if index.is_none() && template::variables(t)?.contains(&"index") {
+            continue;
+        }
+        fields.insert(name.clone(), template::render(t, &values)?);
+    }
+
+    Ok(fields)
+}
+
+async fn add_card_anki(
+    anki: &AnkiClient,
+    config: &WorkerConfig,
+    word: &NihongoWordsGrouped
+) -> Result<()> {
+    let mut rng = rand::thread_rng();
+    let range = rng.gen_range(1001..20000);
+
+    anki.add_note(&NewNote {
+        deck_name: config.anki.deck.clone(),
+        model_name: config.anki.note_type.clone(),
+        fields: note_fields(word, &config.fields, Some(range))?,
+        options: Some(NoteOptions {
+            allow_duplicate: false,
+            duplicate_scope: "deck".to_string(),
+            duplicate_scope_options: Some(DuplicateScopeOptions {
+                deck_name: config.anki.deck.clone(),
+                check_children: false,
+                check_all_models: false
+            })
+        }),
+        tags: note_tags(word)
+    }).await?;
+
     Ok(())
 }
 
-async fn sync_anki() -> Result<()> {
-    let res: Value = Client::new()
-        .post("http://localhost:8765")
-        .json(&serde_json::json!({
-            "action": "sync",
-            "version": 6
-        }))
-        .send()
-        .await?
-        .json()
-        .await?;
+/// Leaves the `{{index}}` fields untouched so the card keeps its place in the deck
+async fn update_card_anki(anki: &AnkiClient, config: &WorkerConfig, note_id: i64, word: &NihongoWordsGrouped) -> Result<()> {
+    anki.update_note_fields(note_id, &note_fields(word, &config.fields, None)?).await?;
 
-    if !res["error"].is_null() {
-        bail!("Response from anki contained error(s) | error(s): {}", res["error"]) 
-    }
+    // updateNoteFields leaves the tags alone, a source added since the note was created still gets tagged
+    anki.add_tags(&[note_id], &note_tags(word)).await?;
 
     Ok(())
 }
 
+/// `lang-crack` plus `source::<title>` and `site::<host>` when the word has a source
+fn note_tags(word: &NihongoWordsGrouped) -> Vec<String> {
+    let mut tags = vec!["lang-crack".to_string()];
+
+    if let Some(title) = word.source_title.as_deref().map(anki_tag).filter(|t| !t.is_empty()) {
+        tags.push(format!("source::{}", title));
+    }
+
+    let host = word.source_url.as_deref()
+        .and_then(|u| reqwest::Url::parse(u).ok())
+        .and_then(|u| u.host_str().map(|h| anki_tag(h.trim_start_matches("www."))));
+    if let Some(host) = host {
+        tags.push(format!("site::{}", host));
+    }
+
+    tags
+}
+
+/// Anki splits tags on whitespace and nests them on `::`
+fn anki_tag(v: &str) -> String {
+    v.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("_")
+        .replace("::", ":")
+        .replace('"', "")
+}
+
+/// Looks the note up by the field set to `{{word}}`, a word that was renamed gets a new note
+async fn find_note_anki(anki: &AnkiClient, deck: &str, word_field: &str, word: &str) -> Result<Option<i64>> {
+    let query = format!("\"deck:{}\" \"{}:{}\"", escape_anki_search(deck), escape_anki_search(word_field), escape_anki_search(word));
+    let ids = anki.find_notes(&query).await?;
+
+    Ok(ids.first().copied())
+}
+
+/// Escapes the characters that are special inside a quoted anki search term
+fn escape_anki_search(v: &str) -> String {
+    let mut escaped = String::with_capacity(v.len());
+    for c in v.chars() {
+        if matches!(c, '\\' | '"' | '*' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
 
 #[derive(Debug, Clone, PartialEq)]
 struct NihongoWordsGrouped {
@@ -247,7 +420,11 @@ struct NihongoWordsGrouped {
     pub spoken_mnemonic: Option<String>,
     pub word_reading: String,
     pub sentence_translation: String,
-    pub tenses: Vec<NihongoWordTense>
+    pub tenses: Vec<NihongoWordTense>,
+    /// Extra example sentences, loaded while processing the word
+    pub sentences: Vec<NihongoWordSentence>,
+    pub source_title: Option<String>,
+    pub source_url: Option<String>
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -256,3 +433,131 @@ struct NihongoWordTense {
     pub tense_sentence: Option<String>,
     pub tense_type: Option<String>
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word() -> NihongoWordsGrouped {
+        NihongoWordsGrouped {
+            id: 1,
+            word: "食べる".to_string(),
+            is_kanji: true,
+            definition: "to eat".to_string(),
+            sentence: "文".to_string(),
+            kanji_mnemonic: None,
+            spoken_mnemonic: None,
+            word_reading: "たべる".to_string(),
+            sentence_translation: "Sentence".to_string(),
+            tenses: vec![],
+            sentences: vec![],
+            source_title: None,
+            source_url: None
+        }
+    }
+
+    #[test]
+    fn note_fields_render_every_sentence() {
+        let sentence = |id: i64, status: &str| NihongoWordSentence {
+            id,
+            word_id: 1,
+            sentence: format!("文{}", id),
+            sentence_translation: format!("Sentence {}", id),
+            source: None,
+            audio_status: status.to_string(),
+            created_at: Default::default()
+        };
+        let word = NihongoWordsGrouped { sentences: vec![sentence(2, "generated"), sentence(3, "failed")], ..word() };
+
+        let templates = WorkerConfig::default().fields;
+        let fields = note_fields(&word, &templates, None).unwrap();
+        assert_eq!(fields["Example Sentence"], "文<br>文2<br>文3");
+        assert_eq!(fields["Sentence Translation"], "Sentence<br>Sentence 2<br>Sentence 3");
+        assert_eq!(fields["sentence_audio"], "[sound:lang_crack_audio_sentence_食べる_たべる.mp3][sound:lang_crack_audio_sentence_2_食べる_たべる.mp3]");
+        assert_eq!(fields["kanji_mnemonic"], "");
+        assert!(!fields.contains_key("Index"));
+
+        let fields = note_fields(&word, &templates, Some(1234)).unwrap();
+        assert_eq!(fields["Index"], "1234");
+        assert_eq!(fields.len(), templates.len());
+
+        let templates = BTreeMap::from([
+            ("Front".to_string(), "{{word}}[{{reading}}]".to_string()),
+            ("Back".to_string(), "{{definition}}<br>{{sentence}}".to_string())
+        ]);
+        let fields = note_fields(&word, &templates, Some(1234)).unwrap();
+        assert_eq!(fields, BTreeMap::from([("Front".to_string(), "食べる[たべる]".to_string()), ("Back".to_string(), "to eat<br>文".to_string())]));
+    }
+
+    #[test]
+    fn every_template_variable_has_a_value() {
+        let word = word();
+
+        let mut names = word_values(&word).into_keys().chain(["index"]).collect::<Vec<&str>>();
+        let mut expected = template::VARIABLES.to_vec();
+        names.sort();
+        expected.sort();
+        assert_eq!(names, expected);
+    }
+
+    #[test]
+    fn tags_note_with_source() {
+        let mut word = word();
+        assert_eq!(note_tags(&word), vec!["lang-crack"]);
+
+        word.source_title = Some(" よつばと！ 1巻::\"特装版\" ".to_string());
+        word.source_url = Some("https://www3.nhk.or.jp/news/easy/k10014345671000/k10014345671000.html".to_string());
+        assert_eq!(note_tags(&word), vec!["lang-crack", "source::よつばと！_1巻:特装版", "site::www3.nhk.or.jp"]);
+
+        word.source_title = Some("  ".to_string());
+        word.source_url = Some("not a url".to_string());
+        assert_eq!(note_tags(&word), vec!["lang-crack"]);
+    }
+
+    #[tokio::test]
+    async fn media_falls_back_to_the_media_dir() {
+        let dir = std::env::temp_dir().join(format!("lang_crack_media_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // nothing listens on the port, so AnkiConnect is unreachable
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let media = MediaStore { anki: AnkiClient::new(Some(&url), None), fallback_dir: Some(dir.clone()) };
+        media.save("lang_crack_audio_word_食べる_たべる.mp3", vec![1, 2, 3]).await.unwrap();
+
+        let written = std::fs::read(dir.join("lang_crack_audio_word_食べる_たべる.mp3")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, vec![1, 2, 3]);
+
+        let media = MediaStore { anki: AnkiClient::new(Some(&url), None), fallback_dir: Some(dir.clone()) };
+        let e = media.save("lang_crack_audio_word_食べる_たべる.mp3", vec![1, 2, 3]).await.unwrap_err().to_string();
+        assert!(e.contains("AnkiConnect error: error sending request"), "{}", e);
+        assert!(e.contains(&format!("{} error:", dir.display())), "{}", e);
+
+        let media = MediaStore { anki: AnkiClient::new(Some(&url), None), fallback_dir: None };
+        let e = media.save("lang_crack_audio_word_食べる_たべる.mp3", vec![1, 2, 3]).await.unwrap_err();
+        assert!(e.downcast_ref::<reqwest::Error>().is_some_and(|e| e.is_connect()), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn media_returns_anki_connect_errors() {
+        let (url, req) = services::anki::mock::serve_once(serde_json::json!({ "result": null, "error": "collection is not available" })).await;
+
+        let dir = std::env::temp_dir().join(format!("lang_crack_media_error_{}", std::process::id()));
+        let media = MediaStore { anki: AnkiClient::new(Some(&url), None), fallback_dir: Some(dir.clone()) };
+        let e = media.save("lang_crack_audio_word_食べる_たべる.mp3", vec![1, 2, 3]).await.unwrap_err();
+
+        assert_eq!(e.to_string(), "Response from anki contained error(s) | action: storeMediaFile | error(s): collection is not available");
+        assert!(!dir.exists());
+        assert_eq!(req.await.unwrap()["action"], "storeMediaFile");
+    }
+
+    #[test]
+    fn escapes_anki_search() {
+        assert_eq!(escape_anki_search("食べる"), "食べる");
+        assert_eq!(escape_anki_search(r#"a_b*c"d\e"#), r#"a\_b\*c\"d\\e"#);
+    }
+}
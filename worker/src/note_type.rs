@@ -0,0 +1,95 @@
+use std::collections::BTreeSet;
+use anyhow::{Result, bail};
+use services::anki::{AnkiClient, NewModel, CardTemplate};
+
+use crate::config::WorkerConfig;
+
+/// Fields of the bundled note type in the order anki shows them, `Word` first as the sort field
+pub const FIELDS: &[&str] = &[
+    "Word",
+    "Word With Reading",
+    "Definition",
+    "Example Sentence",
+    "Sentence Translation",
+    "word_audio",
+    "sentence_audio",
+    "Kanji",
+    "kanji_mnemonic",
+    "spoken_mnemonic",
+    "tenses",
+    "Index"
+];
+
+const FRONT: &str = include_str!("../note_type/front.html");
+const BACK: &str = include_str!("../note_type/back.html");
+const CSS: &str = include_str!("../note_type/style.css");
+
+/// The bundled note type, the one the default `[fields]` mapping is written for
+pub fn bundled_model(name: &str) -> NewModel {
+    NewModel {
+        model_name: name.to_string(),
+        in_order_fields: FIELDS.iter().map(|f| f.to_string()).collect(),
+        css: CSS.to_string(),
+        is_cloze: false,
+        card_templates: vec![CardTemplate {
+            name: "Recognition".to_string(),
+            front: FRONT.to_string(),
+            back: BACK.to_string()
+        }]
+    }
+}
+
+/// Creates the deck, and the bundled note type when the fields are the default ones, if they don't exist yet.
+/// A missing note type with custom fields can't be guessed, so that fails instead.
+pub async fn ensure_deck_and_note_type(anki: &AnkiClient, config: &WorkerConfig) -> Result<()> {
+    if !anki.deck_names().await?.contains(&config.anki.deck) {
+        anki.create_deck(&config.anki.deck).await?;
+        tracing::info!("Created deck: {}", config.anki.deck);
+    }
+
+    if anki.model_names().await?.contains(&config.anki.note_type) {
+        return Ok(());
+    }
+
+    if !uses_bundled_fields(config) {
+        bail!(
+            "Note type {} doesn't exist in anki, create it with the fields {} or remove [fields] from the worker config to use the bundled one",
+            config.anki.note_type,
+            config.fields.keys().cloned().collect::<Vec<String>>().join(", ")
+        );
+    }
+
+    anki.create_model(&bundled_model(&config.anki.note_type)).await?;
+    tracing::info!("Created note type: {}", config.anki.note_type);
+
+    Ok(())
+}
+
+fn uses_bundled_fields(config: &WorkerConfig) -> bool {
+    config.fields.keys().map(|k| k.as_str()).collect::<BTreeSet<&str>>() == FIELDS.iter().copied().collect::<BTreeSet<&str>>()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_model_matches_the_default_fields() {
+        let mut config = WorkerConfig::default();
+        assert!(uses_bundled_fields(&config));
+
+        let model = bundled_model("JP1Kv3");
+        assert_eq!(model.in_order_fields[0], config.word_field().unwrap());
+        // every field the card templates refer to exists
+        for t in [FRONT, BACK] {
+            for name in crate::template::variables(t).unwrap() {
+                let name = name.trim_start_matches(['#', '^', '/']);
+                assert!(name == "FrontSide" || FIELDS.contains(&name), "{}", name);
+            }
+        }
+
+        config.fields.remove("tenses");
+        assert!(!uses_bundled_fields(&config));
+    }
+}
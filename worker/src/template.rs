@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use anyhow::{Result, bail};
+
+/// Everything a note field template can use
+pub const VARIABLES: &[&str] = &[
+    "word",
+    "reading",
+    "definition",
+    "is_kanji",
+    "sentence",
+    "sentence_translation",
+    "sentences",
+    "sentence_translations",
+    "word_audio",
+    "sentence_audio",
+    "kanji_mnemonic",
+    "spoken_mnemonic",
+    "tenses",
+    "source_title",
+    "source_url",
+    "index"
+];
+
+/// Names of the `{{var}}` placeholders in `template`, in order
+pub fn variables(template: &str) -> Result<Vec<&str>> {
+    let mut vars = vec![];
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            bail!("Unclosed {{{{ in template: {}", template);
+        };
+
+        vars.push(rest[start + 2..start + 2 + len].trim());
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    Ok(vars)
+}
+
+/// Replaces every `{{var}}` (whitespace inside the braces is ignored) with its value
+pub fn render(template: &str, values: &HashMap<&str, String>) -> Result<String> {
+    let mut res = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            bail!("Unclosed {{{{ in template: {}", template);
+        };
+
+        let name = rest[start + 2..start + 2 + len].trim();
+        let Some(value) = values.get(name) else {
+            bail!("Unknown template variable: {}", name);
+        };
+
+        res.push_str(&rest[..start]);
+        res.push_str(value);
+        rest = &rest[start + 2 + len + 2..];
+    }
+    res.push_str(rest);
+
+    Ok(res)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_variables() {
+        let values = HashMap::from([("word", "食べる".to_string()), ("reading", "たべる".to_string())]);
+
+        assert_eq!(render("{{word}}[{{ reading }}]", &values).unwrap(), "食べる[たべる]");
+        assert_eq!(render("no vars", &values).unwrap(), "no vars");
+        assert_eq!(render("{{definition}}", &values).unwrap_err().to_string(), "Unknown template variable: definition");
+        assert_eq!(render("{{word", &values).unwrap_err().to_string(), "Unclosed {{ in template: {{word");
+
+        assert_eq!(variables(" {{word}}[{{reading}}]").unwrap(), vec!["word", "reading"]);
+    }
+}